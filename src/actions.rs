@@ -1,30 +1,36 @@
 use colored::*;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::cli::InitFlags;
+use crate::aggregate;
+use crate::cli::{ConflictFlags, InitFlags, PreviewFlags};
+use crate::diff;
+use crate::diff::Change;
+use crate::filter::Filter;
+use crate::log;
 use crate::models::config::Config;
-use crate::models::environment_files::list_files;
 use crate::models::files;
 use crate::models::files::*;
+use crate::models::manifest::{Entry, Manifest};
 use crate::models::workflow::Workflow;
 use crate::models::workspace::Workspace;
+use crate::plan;
+use crate::plan::{Collision, NameIndex, Plan, Status};
+use crate::sync;
+use crate::sync::{Action, ConflictOptions};
 use crate::utils;
 
 pub fn list(workspace: &Workspace, target: &str) {
-    let t = Path::new(target);
+    let filter = Filter::new(workspace);
 
-    list_files(t)
+    plan::generated(target)
         .iter()
-        .filter(|f| {
-            utils::is_workflow_file(f)
-                && f.file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap_or("")
-                    .starts_with(&workspace.name)
+        .filter(|(f, provenance)| {
+            provenance.workspace == workspace.name
+                && !filter.is_ignored(Path::new(&provenance.source))
+                && utils::is_workflow_file(f)
         })
-        .map(|f| {
+        .map(|(f, _)| {
             (
                 Workflow::load(f).expect("invalid workflow file"),
                 f.file_name().unwrap().to_str().unwrap_or(""),
@@ -61,54 +67,237 @@ pub fn init(flags: &InitFlags) -> files::Result<Config> {
     Ok(config)
 }
 
-pub fn clean(workspace: Workspace, target: &str) -> std::io::Result<()> {
-    if let Ok(files) = fs::read_dir(workspace.path) {
-        for file in files {
-            match file {
-                Ok(f) => {
-                    if utils::is_workflow_file(&f.path()) {
-                        utils::remove_file(&f.path(), target, &workspace.name)?;
-
-                        println!(
-                            "Removing {}",
-                            utils::target_filename(&f.path(), target, &workspace.name)
-                                .underline()
-                                .blue()
-                        );
-                    }
-                }
-                Err(err) => println!("Failed to delete file: {}", err),
-            }
+/// Deletes the files generated for the workspace.
+/// Returns the number of generated files edited by hand that were left untouched.
+pub fn clean(
+    workspace: &Workspace,
+    config: &Config,
+    flags: &PreviewFlags,
+    conflict_flags: &ConflictFlags,
+) -> std::io::Result<usize> {
+    let changes = sync::changes(workspace, config, Plan::default(), &conflict_flags.into())?;
+    let summary = sync::apply(&changes, workspace, config, &flags.into())?;
+
+    Ok(summary.conflicts)
+}
+
+/// Deletes the files generated for workspaces which are no longer in the config.
+/// Returns the number of generated files edited by hand that were left untouched.
+pub fn clean_removed(
+    config: &Config,
+    flags: &PreviewFlags,
+    conflict_flags: &ConflictFlags,
+) -> std::io::Result<usize> {
+    let files = plan::removed(&Manifest::read(&config.target)?, config);
+
+    sync::prune(files, &config.target, &flags.into(), &conflict_flags.into())
+}
+
+/// Deletes the aggregate workflow.
+pub fn clean_aggregate(
+    config: &Config,
+    flags: &PreviewFlags,
+    conflict_flags: &ConflictFlags,
+) -> std::io::Result<usize> {
+    let workspace = Workspace {
+        name: aggregate::WORKSPACE.into(),
+        ..Default::default()
+    };
+
+    clean(&workspace, config, flags, conflict_flags)
+}
+
+/// Writes the workspace outputs to the target directory.
+/// Returns the number of generated files edited by hand that were left untouched.
+pub fn copy(
+    workspace: &Workspace,
+    config: &Config,
+    flags: &PreviewFlags,
+    conflict_flags: &ConflictFlags,
+) -> std::io::Result<usize> {
+    let plan = plan::workspace(workspace, config)?;
+    let changes = sync::changes(workspace, config, plan, &conflict_flags.into())?;
+    let summary = sync::apply(&changes, workspace, config, &flags.into())?;
+
+    println!("{} skipped", changes.skipped.to_string().yellow());
+
+    if flags.dry_run {
+        println!("{} unchanged", summary.unchanged.to_string().dimmed());
+        println!(
+            "{} to copy",
+            (summary.copied - summary.unchanged).to_string().green()
+        );
+    } else {
+        println!("{} copied", summary.copied.to_string().green());
+    }
+
+    if summary.conflicts > 0 {
+        println!("{} conflicts", summary.conflicts.to_string().red());
+    }
+
+    Ok(summary.conflicts)
+}
+
+/// Writes the aggregate workflow, if configured.
+/// Returns 1 if it was edited by hand or would overwrite a hand-written file, and was left
+/// untouched.
+pub fn aggregate(
+    config: &Config,
+    flags: &PreviewFlags,
+    conflict_flags: &ConflictFlags,
+) -> std::io::Result<usize> {
+    let output = match aggregate::plan(config)? {
+        Some(output) => output,
+        None => return Ok(0),
+    };
+
+    let manifest = Manifest::read(&config.target)?;
+
+    if plan::is_unmanaged(&output.target, &manifest) {
+        let collision = Collision::Unmanaged {
+            target: output.target,
+            source: output.source,
+        };
+        println!("[{}] {}", "COLLISION".white().on_red().bold(), collision);
+
+        return Ok(1);
+    }
+
+    let previous = fs::read(&output.target).ok();
+    let change = match output.status(&manifest) {
+        Status::UpToDate => return Ok(0),
+        Status::Missing => Change::Create,
+        Status::Outdated => Change::Update,
+        Status::Modified if conflict_flags.force => Change::Update,
+        Status::Modified => {
+            println!(
+                "{} {} was edited by hand, use {} to overwrite it",
+                "conflict".red().bold(),
+                output.target.display().to_string().underline().blue(),
+                "--force".bold(),
+            );
+
+            return Ok(1);
         }
+    };
+
+    if flags.is_verbose() {
+        diff::print_change(
+            change,
+            &output.target.display().to_string(),
+            previous.as_deref().unwrap_or_default(),
+            &output.content,
+            flags.diff,
+        );
+    }
+
+    if flags.dry_run {
+        return Ok(0);
+    }
+
+    fs::write(&output.target, &output.content)?;
+    Manifest::update(&config.target, |m| {
+        m.files.insert(
+            utils::file_name(&output.target),
+            Entry::new(aggregate::WORKSPACE, &output.source, &output.content),
+        );
+    })
+    .map_err(std::io::Error::from)?;
+
+    if !flags.is_verbose() {
+        println!(
+            "Generating {}",
+            output.target.display().to_string().underline().blue()
+        );
     }
 
-    Ok(())
+    Ok(0)
 }
 
-pub fn copy(workspace: &Workspace, target: &str) -> notify::Result<()> {
-    let mut copied = 0;
-    let mut skipped = 0;
-
-    if let Ok(content) = fs::read_dir(&workspace.path) {
-        for f in content {
-            match f {
-                Ok(path) => {
-                    let is_workflow = utils::is_workflow_file(&path.path());
-
-                    if is_workflow {
-                        utils::copy_file(&path.path(), target, &workspace.name)?;
-                        copied += 1;
-                    } else {
-                        skipped += 1;
-                    }
-                }
-                Err(err) => println!("Failed to copy: {}", err),
-            }
+/// Reports an aggregate workflow that is missing, outdated or modified.
+pub fn check_aggregate(config: &Config) -> std::io::Result<usize> {
+    let output = match aggregate::plan(config)? {
+        Some(output) => output,
+        None => return Ok(0),
+    };
+
+    let status = match output.status(&Manifest::read(&config.target)?) {
+        Status::UpToDate => return Ok(0),
+        Status::Missing => "missing ".red(),
+        Status::Outdated => "outdated".yellow(),
+        Status::Modified => "modified".red(),
+    };
+
+    println!("{} {}", status, output.target.display());
+
+    Ok(1)
+}
+
+/// Reports workspaces and outputs clashing with each other or with hand-written files.
+/// Returns whether the outputs can be written safely.
+pub fn validate(config: &Config) -> bool {
+    let manifest = match Manifest::read(&config.target) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            log::warn(&err.to_string());
+            return false;
         }
+    };
+    let collisions = plan::collisions(config, &manifest);
+
+    for collision in &collisions {
+        println!("[{}] {}", "COLLISION".white().on_red().bold(), collision);
     }
 
-    println!("{} skipped", skipped.to_string().yellow());
-    println!("{} copied", copied.to_string().green());
+    collisions.is_empty()
+}
+
+/// Warns about `workflow_run` references that could trigger on workflows of other workspaces.
+pub fn warn_ambiguities(workspace: &Workspace, config: &Config, index: &NameIndex) {
+    for ambiguity in plan::ambiguities(workspace, config, index) {
+        log::warn(&ambiguity.to_string());
+    }
+}
+
+/// Reports generated files that are missing, outdated or orphaned.
+/// Returns the number of files out of sync.
+pub fn check(workspace: &Workspace, config: &Config) -> std::io::Result<usize> {
+    let plan = plan::workspace(workspace, config)?;
+    let changes = sync::changes(workspace, config, plan, &ConflictOptions::default())?;
+    let mut count = 0;
+
+    for action in &changes.actions {
+        let (status, file) = match action {
+            Action::Write(output, Change::Create) => ("missing ".red(), &output.target),
+            Action::Write(output, _) => ("outdated".yellow(), &output.target),
+            Action::Conflict(output) | Action::Merge(output) => ("modified".red(), &output.target),
+            Action::Edited(file) => ("modified".red(), file),
+            Action::Remove(file) if file.exists() => ("orphaned".magenta(), file),
+            Action::Unrecorded(file) => ("orphaned".magenta(), file),
+            Action::UpToDate(_) | Action::Remove(_) => continue,
+        };
+
+        count += 1;
+        println!("{} {}", status, file.display());
+    }
+
+    if count == 0 {
+        println!("{}", "up to date".green());
+    }
+
+    Ok(count)
+}
+
+/// Reports generated files of workspaces which are no longer in the config.
+pub fn check_removed(config: &Config) -> std::io::Result<usize> {
+    let removed: Vec<PathBuf> = plan::removed(&Manifest::read(&config.target)?, config)
+        .into_iter()
+        .filter(|f| f.exists())
+        .collect();
+
+    for orphan in &removed {
+        println!("{} {}", "orphaned".magenta(), orphan.display());
+    }
 
-    Ok(())
+    Ok(removed.len())
 }
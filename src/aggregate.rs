@@ -0,0 +1,278 @@
+//! Single workflow running its jobs once per workspace through a matrix.
+//!
+//! ```yaml
+//! aggregate:
+//!   source: ./.github/templates/ci-all.yml
+//! ```
+//!
+//! Jobs of the source get a `workspace` dimension, next to the ones they already have, and a
+//! `strategy.matrix.include` entry per workspace extending its combinations:
+//!
+//! ```yaml
+//! matrix:
+//!   node: [18, 20]
+//!   workspace: [my-app]
+//!   include:
+//!     - workspace: my-app
+//!       root: packages/my-app
+//!       path: ./packages/my-app/workflows
+//!       vars:
+//!         region: eu-west-1
+//! ```
+use serde_yaml::{Mapping, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::header::Provenance;
+use crate::models::config::Config;
+use crate::models::files::File;
+use crate::models::workflow::{Job, Matrix, MatrixConfig, Workflow};
+use crate::models::workspace::Workspace;
+use crate::plan::Output;
+use crate::utils;
+
+/// Workspace recorded in the header and the manifest of the aggregate workflow
+pub const WORKSPACE: &str = "*";
+
+/// Matrix entry of the workspace
+pub fn entry(workspace: &Workspace, config: &Config) -> Value {
+    let mut entry = Mapping::new();
+
+    entry.insert("workspace".into(), workspace.name.clone().into());
+    entry.insert("root".into(), workspace.root().into());
+    entry.insert("path".into(), workspace.path.clone().into());
+
+    let mut vars = Mapping::new();
+
+    for (key, value) in config.vars.iter().chain(workspace.vars.iter()) {
+        vars.insert(key.clone().into(), value.clone());
+    }
+
+    if !vars.is_empty() {
+        entry.insert("vars".into(), Value::Mapping(vars));
+    }
+
+    Value::Mapping(entry)
+}
+
+/// Adds the workspaces matrix to the selected jobs. Jobs with a `${{ }}` matrix or `include`, or
+/// a `workspace` dimension of their own, are left as they are. Other dimensions and `include`
+/// entries are kept: the entries of the workspaces are keyed on `workspace`, each one only
+/// extends the combinations of its workspace.
+pub fn apply(workflow: &mut Workflow, config: &Config, jobs: &[String]) {
+    let names: Vec<Value> = config
+        .workspaces
+        .iter()
+        .map(|w| w.name.clone().into())
+        .collect();
+    let entries: Vec<Value> = config.workspaces.iter().map(|w| entry(w, config)).collect();
+
+    for (name, job) in workflow.jobs.iter_mut() {
+        if !jobs.is_empty() && !jobs.contains(name) {
+            continue;
+        }
+
+        let strategy = match job {
+            Job::Normal(job) => &mut job.strategy,
+            Job::Reusable(job) => &mut job.strategy,
+        };
+
+        let strategy = strategy.get_or_insert_with(Default::default);
+
+        match strategy
+            .matrix
+            .get_or_insert_with(|| Matrix::Config(MatrixConfig::default()))
+        {
+            Matrix::Config(matrix) => {
+                if matrix.dimensions.contains_key("workspace") {
+                    continue;
+                }
+
+                match &mut matrix.include {
+                    Some(Value::Sequence(include)) => include.extend(entries.iter().cloned()),
+                    Some(_) => continue,
+                    None => matrix.include = Some(Value::Sequence(entries.clone())),
+                }
+
+                matrix
+                    .dimensions
+                    .insert("workspace".into(), Value::Sequence(names.clone()));
+            }
+            Matrix::Expression(_) => continue,
+        }
+    }
+}
+
+/// Plans the aggregate workflow, if configured.
+pub fn plan(config: &Config) -> io::Result<Option<Output>> {
+    let aggregate = match &config.aggregate {
+        Some(aggregate) => aggregate,
+        None => return Ok(None),
+    };
+
+    let source = PathBuf::from(&aggregate.source);
+    let original = fs::read(&source)?;
+    let provenance = Provenance {
+        workspace: WORKSPACE.into(),
+        source: utils::normalize_path(&source),
+        hash: utils::hash(&original),
+    };
+
+    let mut workflow = Workflow::load(&source)?;
+    apply(&mut workflow, config, &aggregate.jobs);
+
+    let mut content = provenance.render(config.header()).into_bytes();
+    content.extend(workflow.to_yaml()?.into_bytes());
+
+    Ok(Some(Output {
+        target: Path::new(&config.target).join(aggregate.filename()),
+        source,
+        content,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        let mut config = Config::new(".github/workflows");
+        config.workspaces = ["my-app", "api"]
+            .iter()
+            .map(|name| Workspace {
+                name: name.to_string(),
+                path: format!("./packages/{}/workflows", name),
+                ..Default::default()
+            })
+            .collect();
+
+        config
+    }
+
+    fn matrix(workflow: &Workflow, job: &str) -> Value {
+        let yaml: Value = serde_yaml::to_value(workflow).unwrap();
+
+        yaml["jobs"][job]["strategy"]["matrix"].clone()
+    }
+
+    fn include(workflow: &Workflow, job: &str) -> Option<Value> {
+        matrix(workflow, job).get("include").cloned()
+    }
+
+    /// Combinations the runner expands the matrix to: the product of the dimensions, extended by
+    /// the `include` entries that don't overwrite one of their values, or added as new ones.
+    fn combinations(matrix: &Value) -> Vec<Mapping> {
+        let matrix = matrix.as_mapping().unwrap();
+        let mut combinations = vec![Mapping::new()];
+
+        for (key, values) in matrix {
+            if key == "include" || key == "exclude" {
+                continue;
+            }
+
+            combinations = combinations
+                .iter()
+                .flat_map(|c| {
+                    values.as_sequence().unwrap().iter().map(move |value| {
+                        let mut c = c.clone();
+                        c.insert(key.clone(), value.clone());
+                        c
+                    })
+                })
+                .collect();
+        }
+
+        let original = combinations.clone();
+        let mut added = Vec::new();
+
+        for entry in matrix["include"].as_sequence().unwrap() {
+            let entry = entry.as_mapping().unwrap();
+            let mut extended = false;
+
+            for (combination, original) in combinations.iter_mut().zip(&original) {
+                let fits = entry
+                    .iter()
+                    .all(|(key, value)| original.get(key).is_none_or(|v| v == value));
+
+                if fits {
+                    for (key, value) in entry {
+                        combination.insert(key.clone(), value.clone());
+                    }
+                    extended = true;
+                }
+            }
+
+            if !extended {
+                added.push(entry.clone());
+            }
+        }
+
+        combinations.extend(added);
+        combinations
+    }
+
+    fn workflow(strategy: &str) -> Workflow {
+        serde_yaml::from_str(&format!(
+            "name: CI\non: push\njobs:\n  test:\n    runs-on: ubuntu-latest\n{}    steps:\n      - run: make\n",
+            strategy
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn adds_the_workspaces() {
+        let mut workflow = workflow("");
+        apply(&mut workflow, &config(), &[]);
+
+        let combinations = combinations(&matrix(&workflow, "test"));
+        assert_eq!(combinations.len(), 2);
+        assert_eq!(combinations[0]["workspace"], "my-app");
+        assert_eq!(combinations[0]["path"], "./packages/my-app/workflows");
+        assert_eq!(combinations[1]["workspace"], "api");
+        assert_eq!(combinations[1]["path"], "./packages/api/workflows");
+    }
+
+    #[test]
+    fn keeps_existing_dimensions_and_include_entries() {
+        let mut workflow = workflow(
+            "    strategy:\n      matrix:\n        node: [18, 20]\n        include:\n          - node: 20\n            experimental: true\n",
+        );
+        apply(&mut workflow, &config(), &[]);
+
+        let combinations = combinations(&matrix(&workflow, "test"));
+        assert_eq!(combinations.len(), 4, "{:?}", combinations);
+
+        for combination in &combinations {
+            let workspace = combination["workspace"].as_str().unwrap();
+            let node = combination["node"].as_u64().unwrap();
+
+            assert_eq!(
+                combination["path"],
+                format!("./packages/{}/workflows", workspace).as_str()
+            );
+            assert_eq!(combination.get("experimental").is_some(), node == 20);
+        }
+    }
+
+    #[test]
+    fn leaves_workspace_dimension_alone() {
+        let mut workflow = workflow("    strategy:\n      matrix:\n        workspace: [docs]\n");
+        apply(&mut workflow, &config(), &[]);
+
+        assert_eq!(include(&workflow, "test"), None);
+    }
+
+    #[test]
+    fn leaves_expression_include_alone() {
+        let mut workflow = workflow(
+            "    strategy:\n      matrix:\n        include: ${{ fromJSON(needs.a.outputs.b) }}\n",
+        );
+        apply(&mut workflow, &config(), &[]);
+
+        assert_eq!(
+            include(&workflow, "test").unwrap(),
+            "${{ fromJSON(needs.a.outputs.b) }}"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! YAML merge keys (`<<`) and the source lines behind them.
+//!
+//! Aliases are resolved by the parser, merge keys are applied here so the workflow model never
+//! sees them. When the same key comes from several merged maps the first one wins, as in the
+//! YAML spec, and the conflict is reported with the lines of both anchors.
+use serde_yaml::{Mapping, Value};
+
+use crate::edit::Document;
+
+const MERGE_KEY: &str = "<<";
+
+/// A key provided by two maps merged into the same map
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    /// Path of the map holding the `<<` key
+    pub path: Vec<String>,
+    pub key: String,
+    /// Positions of the merged maps, the first one wins
+    pub merged: [usize; 2],
+}
+
+/// Applies the merge keys of the value. Returns the keys merged from several maps.
+pub fn apply_merges(value: &mut Value) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+
+    merge(value, &mut Vec::new(), &mut conflicts);
+    conflicts
+}
+
+fn merge(value: &mut Value, path: &mut Vec<String>, conflicts: &mut Vec<Conflict>) {
+    match value {
+        Value::Sequence(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                path.push(i.to_string());
+                merge(item, path, conflicts);
+                path.pop();
+            }
+        }
+        Value::Mapping(mapping) => {
+            if mapping.contains_key(MERGE_KEY) {
+                *mapping = merge_mapping(std::mem::take(mapping), path, conflicts);
+            }
+
+            for (key, value) in mapping.iter_mut() {
+                path.push(key_name(key));
+                merge(value, path, conflicts);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replaces the merge key by the keys of the merged maps the map doesn't define itself.
+fn merge_mapping(mapping: Mapping, path: &[String], conflicts: &mut Vec<Conflict>) -> Mapping {
+    let mut merged = Mapping::with_capacity(mapping.len());
+
+    for (key, value) in &mapping {
+        if key != MERGE_KEY {
+            merged.insert(key.clone(), value.clone());
+            continue;
+        }
+
+        let sources = match value {
+            Value::Sequence(sources) => sources.clone(),
+            source => vec![source.clone()],
+        };
+
+        // key -> position of the merged map it comes from
+        let mut origins: Vec<(Value, usize)> = Vec::new();
+
+        for (i, source) in sources.into_iter().enumerate() {
+            let mut source = source;
+
+            // merged maps can have merge keys themselves
+            merge(&mut source, &mut path.to_vec(), conflicts);
+
+            let source = match source {
+                Value::Mapping(source) => source,
+                _ => continue,
+            };
+
+            for (key, value) in source {
+                if mapping.contains_key(&key) {
+                    continue;
+                }
+
+                match origins.iter().find(|(k, _)| k == &key) {
+                    Some((_, first)) => {
+                        if merged.get(&key) != Some(&value) {
+                            conflicts.push(Conflict {
+                                path: path.to_vec(),
+                                key: key_name(&key),
+                                merged: [*first, i],
+                            });
+                        }
+                    }
+                    None => {
+                        origins.push((key.clone(), i));
+                        merged.insert(key, value);
+                    }
+                }
+            }
+        }
+    }
+
+    merged
+}
+
+fn key_name(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        key => serde_yaml::to_string(key)
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// A `key:` line of a YAML document and its path, list items being numbered from 0
+#[derive(Debug, Clone)]
+struct Line {
+    number: usize,
+    path: Vec<String>,
+    /// What follows the colon
+    value: String,
+}
+
+/// Locates keys in the block style YAML source. Flow collections are not indexed.
+pub struct Lines {
+    lines: Vec<Line>,
+}
+
+impl Lines {
+    pub fn new(content: &str) -> Lines {
+        let lines = Document::parse(content)
+            .nodes
+            .into_iter()
+            .filter(|n| !n.is_item)
+            .map(|n| Line {
+                number: n.line + 1,
+                path: n.path,
+                value: n.value,
+            })
+            .collect();
+
+        Lines { lines }
+    }
+
+    /// Line of the key at the given path
+    pub fn find(&self, path: &[String]) -> Option<usize> {
+        self.lines.iter().find(|l| l.path == path).map(|l| l.number)
+    }
+
+    /// Path of the map defined with `&name`
+    fn anchor(&self, name: &str) -> Option<&[String]> {
+        let anchor = format!("&{}", name);
+
+        self.lines
+            .iter()
+            .find(|l| l.value.split_whitespace().next() == Some(anchor.as_str()))
+            .map(|l| l.path.as_slice())
+    }
+
+    /// Anchors referenced by the merge key of the map at the given path
+    fn merged_anchors(&self, path: &[String]) -> Vec<String> {
+        let mut merge_path = path.to_vec();
+        merge_path.push(MERGE_KEY.into());
+
+        self.lines
+            .iter()
+            .find(|l| l.path == merge_path)
+            .map(|l| {
+                l.value
+                    .split(|c: char| c == '[' || c == ']' || c == ',' || c.is_whitespace())
+                    .filter_map(|a| a.strip_prefix('*'))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Describes the conflict with the source lines of both values.
+    pub fn describe(&self, conflict: &Conflict) -> String {
+        let anchors = self.merged_anchors(&conflict.path);
+        let mut key_path = conflict.path.clone();
+        key_path.push(MERGE_KEY.into());
+        let line = self.find(&key_path).unwrap_or_default();
+
+        let origin = |i: usize| match anchors.get(i) {
+            Some(anchor) => {
+                let line = self.anchor(anchor).and_then(|path| {
+                    let mut path = path.to_vec();
+                    path.push(conflict.key.clone());
+                    self.find(&path)
+                });
+
+                match line {
+                    Some(line) => format!("*{} (line {})", anchor, line),
+                    None => format!("*{}", anchor),
+                }
+            }
+            None => format!("merged map #{}", i + 1),
+        };
+
+        let mut output = conflict.path.clone();
+        output.push(conflict.key.clone());
+
+        format!(
+            "{}: `{}` is merged from both {} and {}, using {}",
+            line,
+            output.join("."),
+            origin(conflict.merged[0]),
+            origin(conflict.merged[1]),
+            origin(conflict.merged[0])
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+x-defaults: &defaults
+  runs-on: ubuntu-latest
+  timeout-minutes: 10
+x-linux: &linux
+  runs-on: ubuntu-22.04
+  timeout-minutes: 10
+jobs:
+  build:
+    <<: [*defaults, *linux]
+    steps: []
+  test:
+    <<: *defaults
+    runs-on: macos-latest
+";
+
+    fn merged() -> (Value, Vec<Conflict>) {
+        let mut value: Value = serde_yaml::from_str(SOURCE).unwrap();
+        let conflicts = apply_merges(&mut value);
+
+        (value, conflicts)
+    }
+
+    #[test]
+    fn first_merged_map_wins() {
+        let (value, _) = merged();
+        let build = &value["jobs"]["build"];
+
+        assert_eq!(build["runs-on"], "ubuntu-latest");
+        assert_eq!(build["timeout-minutes"], 10);
+        assert_eq!(build.get(MERGE_KEY), None);
+    }
+
+    #[test]
+    fn own_keys_win_over_merged_ones() {
+        let (value, _) = merged();
+
+        assert_eq!(value["jobs"]["test"]["runs-on"], "macos-latest");
+        assert_eq!(value["jobs"]["test"]["timeout-minutes"], 10);
+    }
+
+    #[test]
+    fn only_different_values_conflict() {
+        let (_, conflicts) = merged();
+
+        assert_eq!(conflicts.len(), 1, "{:?}", conflicts);
+        assert_eq!(conflicts[0].path, ["jobs", "build"]);
+        assert_eq!(conflicts[0].key, "runs-on");
+        assert_eq!(conflicts[0].merged, [0, 1]);
+    }
+
+    #[test]
+    fn describes_conflicts_with_their_lines() {
+        let (_, conflicts) = merged();
+        let lines = Lines::new(SOURCE);
+
+        assert_eq!(
+            lines.describe(&conflicts[0]),
+            "9: `jobs.build.runs-on` is merged from both *defaults (line 2) and *linux (line 5), using *defaults (line 2)"
+        );
+    }
+}
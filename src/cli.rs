@@ -1,5 +1,7 @@
 use clap::Parser;
 
+use crate::sync::{ConflictOptions, PreviewOptions};
+
 #[derive(Parser, Debug, Clone)]
 pub struct CopyFlags {
     /// Watch for changes
@@ -7,6 +9,53 @@ pub struct CopyFlags {
     pub watch: bool,
 }
 
+#[derive(Parser, Clone, Debug, Default)]
+pub struct PreviewFlags {
+    /// Print planned changes without writing or deleting files
+    #[clap(long, global = true, value_parser, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Print a unified diff of the changes to generated files
+    #[clap(long, global = true, value_parser, default_value_t = false)]
+    pub diff: bool,
+}
+
+impl PreviewFlags {
+    /// Whether planned operations should be printed
+    pub fn is_verbose(&self) -> bool {
+        self.dry_run || self.diff
+    }
+}
+
+impl From<&PreviewFlags> for PreviewOptions {
+    fn from(flags: &PreviewFlags) -> Self {
+        PreviewOptions {
+            dry_run: flags.dry_run,
+            diff: flags.diff,
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug, Default)]
+pub struct ConflictFlags {
+    /// Overwrite generated files edited by hand
+    #[clap(long, global = true, value_parser, default_value_t = false)]
+    pub force: bool,
+
+    /// Merge changes made to generated files back into their source workflow
+    #[clap(long, global = true, value_parser, default_value_t = false)]
+    pub merge: bool,
+}
+
+impl From<&ConflictFlags> for ConflictOptions {
+    fn from(flags: &ConflictFlags) -> Self {
+        ConflictOptions {
+            force: flags.force,
+            merge: flags.merge,
+        }
+    }
+}
+
 #[derive(Parser, Clone, Debug)]
 pub struct InitFlags {
     /// Use json instead of yaml
@@ -35,6 +84,9 @@ pub enum Action {
 
     /// List workflows in the `target` directory
     List,
+
+    /// Fail if files in the `target` directory are missing, outdated or orphaned
+    Check,
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -54,4 +106,10 @@ pub struct Args {
 
     #[clap(global = true, short, long, value_parser, default_value_t = false)]
     pub watch: bool,
+
+    #[clap(flatten)]
+    pub preview: PreviewFlags,
+
+    #[clap(flatten)]
+    pub conflicts: ConflictFlags,
 }
@@ -0,0 +1,54 @@
+//! Prints planned changes to generated files.
+use colored::*;
+use similar::{ChangeTag, TextDiff};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Create,
+    Update,
+    Delete,
+}
+
+impl Change {
+    fn label(&self) -> ColoredString {
+        match self {
+            Change::Create => "create".green(),
+            Change::Update => "update".yellow(),
+            Change::Delete => "delete".red(),
+        }
+    }
+}
+
+/// Prints the operation and, if `with_diff`, the unified diff between `old` and `new`.
+pub fn print_change(change: Change, path: &str, old: &[u8], new: &[u8], with_diff: bool) {
+    println!("{} {}", change.label(), path.underline().blue());
+
+    if with_diff {
+        print_unified(
+            path,
+            &String::from_utf8_lossy(old),
+            &String::from_utf8_lossy(new),
+        );
+    }
+}
+
+pub fn print_unified(path: &str, old: &str, new: &str) {
+    let diff = TextDiff::from_lines(old, new);
+
+    println!("{}", format!("--- a/{}", path).bold());
+    println!("{}", format!("+++ b/{}", path).bold());
+
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        println!("{}", hunk.header().to_string().cyan());
+
+        for change in hunk.iter_changes() {
+            let value = change.value().trim_end_matches('\n');
+
+            match change.tag() {
+                ChangeTag::Delete => println!("{}", format!("-{}", value).red()),
+                ChangeTag::Insert => println!("{}", format!("+{}", value).green()),
+                ChangeTag::Equal => println!("{}", format!(" {}", value).dimmed()),
+            }
+        }
+    }
+}
@@ -0,0 +1,641 @@
+//! Surgical edits of YAML documents, keeping comments, key order, quoting and block scalars.
+//!
+//! Transforms work on the workflow model. The differences between the model before and after
+//! them are applied to the source text, only rewriting the lines of the values that changed:
+//!
+//! ```yaml
+//! # Deploy the app      <- kept
+//! name: app / Deploy    <- changed value, the trailing comment is kept
+//! on:
+//!   push:
+//!     paths:            <- inserted key
+//!     - packages/app/**
+//!     branches: [main]  <- kept
+//! ```
+//!
+//! Only block style collections are indexed. A change inside a flow collection (`[a, b]`)
+//! rewrites the whole collection. Values carrying an anchor (`&name`) or an alias (`*name`)
+//! are shared with other parts of the document and are never edited.
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+
+/// A `key:` or `- ` line
+#[derive(Debug, Clone)]
+pub struct Node {
+    /// 0-based
+    pub line: usize,
+    /// Column of the key or the dash
+    pub column: usize,
+    /// Keys and list indexes leading to the node
+    pub path: Vec<String>,
+    pub is_item: bool,
+    /// What follows the colon or the dash on the same line
+    pub value: String,
+    /// Column of the end of the key, colon included
+    key_end: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    lines: Vec<String>,
+    pub nodes: Vec<Node>,
+    /// Indentation of nested maps
+    indent: usize,
+    /// Indentation of list items relative to their key, 0 for `key:\n- item`
+    sequence_indent: usize,
+    trailing_newline: bool,
+}
+
+impl Document {
+    pub fn parse(content: &str) -> Document {
+        let lines: Vec<String> = content.lines().map(String::from).collect();
+        let nodes = scan(&lines);
+
+        let parent = |node: &Node| {
+            nodes
+                .iter()
+                .find(|n| !n.is_item && n.path == node.path[..node.path.len() - 1])
+                .map(|n| n.column)
+        };
+
+        let indent = nodes
+            .iter()
+            .filter(|n| !n.is_item && n.path.len() > 1)
+            .find_map(|n| parent(n).map(|c| n.column.saturating_sub(c)))
+            .filter(|i| *i > 0)
+            .unwrap_or(2);
+        let sequence_indent = nodes
+            .iter()
+            .filter(|n| n.is_item)
+            .find_map(|n| parent(n).map(|c| n.column.saturating_sub(c)))
+            .unwrap_or(2);
+
+        Document {
+            lines,
+            nodes,
+            indent,
+            sequence_indent,
+            trailing_newline: content.ends_with('\n'),
+        }
+    }
+
+    fn reindex(&mut self) {
+        self.nodes = scan(&self.lines);
+    }
+
+    fn find(&self, path: &[String]) -> Option<usize> {
+        let is_index = path.last().is_some_and(|c| c.parse::<usize>().is_ok());
+
+        self.nodes
+            .iter()
+            .position(|n| n.path == path && n.is_item == is_index)
+    }
+
+    /// First line after the node and its value, trailing blank lines and comments excluded
+    fn end(&self, index: usize) -> usize {
+        let node = &self.nodes[index];
+        let mut end = self.nodes[index + 1..]
+            .iter()
+            .find(|n| n.line > node.line && n.column <= node.column)
+            .map(|n| n.line)
+            .unwrap_or(self.lines.len());
+
+        while end > node.line + 1 {
+            let line = &self.lines[end - 1];
+            let text = line.trim_start();
+            let is_outer_comment = text.starts_with('#') && line.len() - text.len() <= node.column;
+
+            if text.is_empty() || is_outer_comment {
+                end -= 1;
+            } else {
+                break;
+            }
+        }
+
+        end
+    }
+
+    /// Whether the value at the path, or a value holding it, carries an anchor or an alias.
+    /// With `nested`, values inside it count too.
+    fn is_shared(&self, path: &[String], nested: bool) -> bool {
+        self.nodes.iter().any(|n| {
+            let is_related = path.starts_with(&n.path) || (nested && n.path.starts_with(path));
+            let value = split_comment(&n.value).0;
+
+            is_related && (value.starts_with('&') || value.starts_with('*'))
+        })
+    }
+
+    /// Replaces the value at the path. Returns false if it cannot be located or is shared.
+    pub fn set(&mut self, path: &[String], value: &Value) -> bool {
+        let index = match self.find(path) {
+            Some(index) if !self.is_shared(path, true) => index,
+            _ => return false,
+        };
+
+        let node = self.nodes[index].clone();
+        let end = self.end(index);
+        let line = &self.lines[node.line];
+        let (inline, comment) = split_comment(&node.value);
+
+        let mut lines = if node.is_item {
+            let mut lines = self.sequence(std::slice::from_ref(value), node.column);
+            lines[0].replace_range(..node.column, &line[..node.column]);
+            lines
+        } else {
+            let is_flow = inline.starts_with('[') || inline.starts_with('{');
+            self.entry(
+                line[..node.key_end].to_string(),
+                value,
+                node.column,
+                is_flow,
+            )
+        };
+
+        if let Some(comment) = comment {
+            lines[0] = format!("{} {}", lines[0], comment);
+        }
+
+        self.lines.splice(node.line..end, lines);
+        self.reindex();
+        true
+    }
+
+    /// Adds a key to the block map at the path, after the given sibling or first.
+    /// Returns false if the map cannot be located, isn't a block map or is shared.
+    pub fn insert(
+        &mut self,
+        path: &[String],
+        key: &str,
+        value: &Value,
+        after: Option<&str>,
+    ) -> bool {
+        if self.is_shared(path, false) {
+            return false;
+        }
+
+        let children: Vec<usize> = (0..self.nodes.len())
+            .filter(|i| {
+                let n = &self.nodes[*i];
+                !n.is_item && n.path.len() == path.len() + 1 && n.path[..path.len()] == *path
+            })
+            .collect();
+
+        let first = match children.first() {
+            Some(first) => *first,
+            None => return false,
+        };
+
+        let column = self.nodes[first].column;
+        let is_on_dash_line = self.nodes[first].column > 0
+            && self
+                .nodes
+                .iter()
+                .any(|n| n.is_item && n.line == self.nodes[first].line);
+
+        let line = match after {
+            Some(after) => {
+                let mut sibling = path.to_vec();
+                sibling.push(after.into());
+
+                match self.find(&sibling) {
+                    Some(index) => self.end(index),
+                    None => return false,
+                }
+            }
+            // the first key of a list item shares its line with the dash
+            None if is_on_dash_line => self.end(first),
+            None => self.nodes[first].line,
+        };
+
+        let key = format!("{}{}:", " ".repeat(column), scalar(&Value::from(key))[0]);
+        let lines = self.entry(key, value, column, false);
+
+        self.lines.splice(line..line, lines);
+        self.reindex();
+        true
+    }
+
+    /// Removes the key at the path. Returns false if it cannot be located or is shared.
+    pub fn remove(&mut self, path: &[String]) -> bool {
+        let index = match self.find(path) {
+            Some(index) if !self.is_shared(path, true) => index,
+            _ => return false,
+        };
+
+        let node = &self.nodes[index];
+
+        // the first key of a list item shares its line with the dash
+        if node.is_item || self.nodes.iter().any(|n| n.is_item && n.line == node.line) {
+            return false;
+        }
+
+        let (start, end) = (node.line, self.end(index));
+        self.lines.drain(start..end);
+        self.reindex();
+        true
+    }
+
+    /// Applies the differences between `old` and `new`, the values of the path.
+    /// Returns false if the changes could not be applied.
+    pub fn update(&mut self, path: &[String], old: &Value, new: &Value) -> bool {
+        if old == new {
+            return true;
+        }
+
+        let applied = match (old, new) {
+            (Value::Mapping(old), Value::Mapping(new)) => self.update_mapping(path, old, new),
+            (Value::Sequence(old), Value::Sequence(new)) if old.len() == new.len() => {
+                old.iter().zip(new).enumerate().all(|(i, (old, new))| {
+                    let mut path = path.to_vec();
+                    path.push(i.to_string());
+                    self.update(&path, old, new)
+                })
+            }
+            _ => false,
+        };
+
+        // rewrites the whole value, over the partial changes
+        applied || (!path.is_empty() && self.set(path, new))
+    }
+
+    fn update_mapping(&mut self, path: &[String], old: &Mapping, new: &Mapping) -> bool {
+        let child = |key: &Value| {
+            let mut child = path.to_vec();
+            child.push(key.as_str()?.to_string());
+            Some(child)
+        };
+
+        for key in old.keys().filter(|k| !new.contains_key(k)) {
+            match child(key) {
+                Some(child) if self.remove(&child) => {}
+                _ => return false,
+            }
+        }
+
+        let mut previous: Option<&str> = None;
+
+        for (key, value) in new {
+            let applied = match (child(key), old.get(key)) {
+                (Some(child), Some(old)) => self.update(&child, old, value),
+                (Some(_), None) => self.insert(path, key.as_str().unwrap(), value, previous),
+                (None, _) => false,
+            };
+
+            if !applied {
+                return false;
+            }
+
+            previous = key.as_str();
+        }
+
+        true
+    }
+
+    /// `key:` line followed by the value, at the column of the key
+    fn entry(&self, key: String, value: &Value, column: usize, is_flow: bool) -> Vec<String> {
+        match value {
+            Value::Mapping(m) if !m.is_empty() => {
+                let mut lines = vec![key];
+                lines.extend(self.mapping(m, column + self.indent));
+                lines
+            }
+            Value::Sequence(s) if is_flow && s.iter().all(is_inline) => {
+                let items: Vec<String> = s.iter().map(|v| scalar(v)[0].clone()).collect();
+                vec![format!("{} [{}]", key, items.join(", "))]
+            }
+            Value::Sequence(s) if !s.is_empty() => {
+                let mut lines = vec![key];
+                lines.extend(self.sequence(s, column + self.sequence_indent));
+                lines
+            }
+            Value::Null => vec![key],
+            value => {
+                let scalar = scalar(value);
+                let mut lines = vec![format!("{} {}", key, scalar[0])];
+
+                // block scalar content, indented by the serializer
+                for line in &scalar[1..] {
+                    lines.push(format!("{}{}", " ".repeat(column), line));
+                }
+
+                lines
+            }
+        }
+    }
+
+    fn mapping(&self, mapping: &Mapping, column: usize) -> Vec<String> {
+        mapping
+            .iter()
+            .flat_map(|(k, v)| {
+                let key = format!("{}{}:", " ".repeat(column), scalar(k)[0]);
+                self.entry(key, v, column, false)
+            })
+            .collect()
+    }
+
+    fn sequence(&self, items: &[Value], column: usize) -> Vec<String> {
+        let dash = format!("{}- ", " ".repeat(column));
+        let mut lines = Vec::new();
+
+        for item in items {
+            let mut item_lines = match item {
+                Value::Mapping(m) if !m.is_empty() => self.mapping(m, column + 2),
+                Value::Sequence(s) if !s.is_empty() => self.sequence(s, column + 2),
+                value => scalar(value)
+                    .iter()
+                    .map(|l| format!("{}{}", " ".repeat(column + 2), l))
+                    .collect(),
+            };
+
+            item_lines[0].replace_range(..column + 2, &dash);
+            lines.extend(item_lines);
+        }
+
+        lines
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.lines.join("\n"))?;
+
+        if self.trailing_newline {
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Applies the changes from `old` to `new` to the document. `None` if they could not be
+/// applied without rewriting the whole document.
+pub fn apply(content: &str, old: &Value, new: &Value) -> Option<String> {
+    let mut document = Document::parse(content);
+
+    document.update(&[], old, new).then(|| document.to_string())
+}
+
+fn is_inline(value: &Value) -> bool {
+    !matches!(value, Value::Mapping(_) | Value::Sequence(_)) && scalar(value).len() == 1
+}
+
+/// Serialized scalar, block scalars span several lines
+fn scalar(value: &Value) -> Vec<String> {
+    let serialized = serde_yaml::to_string(value).unwrap_or_default();
+
+    serialized.trim_end().lines().map(String::from).collect()
+}
+
+/// `value # comment` -> (`value`, `# comment`), quotes aware
+fn split_comment(value: &str) -> (&str, Option<&str>) {
+    let mut quote = None;
+
+    for (i, c) in value.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('#', None) if i == 0 || value[..i].ends_with(' ') => {
+                return (value[..i].trim_end(), Some(&value[i..]));
+            }
+            _ => {}
+        }
+    }
+
+    (value, None)
+}
+
+/// `key: value` -> (`key`, end of the key, `value`)
+fn split_key(text: &str) -> Option<(String, usize, &str)> {
+    if text.starts_with('{') || text.starts_with('[') || text.starts_with('#') {
+        return None;
+    }
+
+    let (key, end) = match text.find(": ") {
+        Some(i) => (&text[..i], i + 1),
+        None => (text.strip_suffix(':')?, text.len()),
+    };
+
+    let value = text[end..].trim();
+    let key = key.trim().trim_matches(|c| c == '"' || c == '\'');
+
+    Some((key.into(), end, value))
+}
+
+/// Indexes the keys and list items of block collections.
+fn scan(lines: &[String]) -> Vec<Node> {
+    // (indentation, component, is a list item)
+    let mut stack: Vec<(usize, String, bool)> = Vec::new();
+    let mut nodes = Vec::new();
+    // indentation of the key holding a block scalar, its lines are skipped
+    let mut block_scalar: Option<usize> = None;
+
+    for (number, line) in lines.iter().enumerate() {
+        let mut text = line.trim_start();
+        let mut column = line.len() - text.len();
+
+        if text.is_empty() || text.starts_with('#') || text == "---" {
+            continue;
+        }
+
+        match block_scalar {
+            Some(indent) if column > indent => continue,
+            _ => block_scalar = None,
+        }
+
+        while let Some(rest) = text
+            .strip_prefix("- ")
+            .or_else(|| text.strip_prefix('-').filter(|r| r.is_empty()))
+        {
+            while stack.last().is_some_and(|(indent, _, _)| *indent > column) {
+                stack.pop();
+            }
+
+            let index = match stack.last() {
+                Some((indent, index, true)) if *indent == column => {
+                    let next = index.parse::<usize>().unwrap_or_default() + 1;
+                    stack.pop();
+                    next
+                }
+                _ => 0,
+            };
+
+            stack.push((column, index.to_string(), true));
+
+            let trimmed = rest.trim_start();
+            let item_value = split_comment(trimmed).0;
+
+            nodes.push(Node {
+                line: number,
+                column,
+                path: stack.iter().map(|(_, c, _)| c.clone()).collect(),
+                is_item: true,
+                value: trimmed.into(),
+                key_end: column + 1,
+            });
+
+            if item_value.starts_with('|') || item_value.starts_with('>') {
+                block_scalar = Some(column);
+            }
+
+            column += text.len() - trimmed.len();
+            text = trimmed;
+        }
+
+        let (key, key_end, value) = match split_key(text) {
+            Some(split) => split,
+            None => continue,
+        };
+
+        while stack.last().is_some_and(|(indent, _, _)| *indent >= column) {
+            stack.pop();
+        }
+
+        stack.push((column, key, false));
+
+        let scalar_value = split_comment(value).0;
+
+        if scalar_value.starts_with('|') || scalar_value.starts_with('>') {
+            block_scalar = Some(column);
+        }
+
+        nodes.push(Node {
+            line: number,
+            column,
+            path: stack.iter().map(|(_, c, _)| c.clone()).collect(),
+            is_item: false,
+            value: value.into(),
+            key_end: column + key_end,
+        });
+    }
+
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies the change to the parsed content, the edited content must read as the new value
+    fn edited(content: &str, change: impl FnOnce(&mut Value)) -> Option<String> {
+        let old: Value = serde_yaml::from_str(content).unwrap();
+        let mut new = old.clone();
+        change(&mut new);
+
+        let edited = apply(content, &old, &new)?;
+        assert_eq!(serde_yaml::from_str::<Value>(&edited).unwrap(), new);
+
+        Some(edited)
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let content = "\
+# Deploy the app
+name: Deploy # shown in the UI
+on: push
+jobs:
+  build:
+    # pinned runner
+    runs-on: ubuntu-22.04
+";
+        let edited = edited(content, |v| v["name"] = "app / Deploy".into()).unwrap();
+
+        assert_eq!(
+            edited,
+            content.replace("name: Deploy #", "name: app / Deploy #")
+        );
+    }
+
+    #[test]
+    fn keeps_block_scalars() {
+        let content = "\
+name: CI
+jobs:
+  build:
+    steps:
+      - run: |
+          make
+          make test
+      - run: >
+          echo folded
+          line
+";
+        let renamed = edited(content, |v| v["name"] = "app / CI".into()).unwrap();
+        assert_eq!(renamed, content.replace("name: CI", "name: app / CI"));
+
+        let edited = edited(content, |v| {
+            v["jobs"]["build"]["steps"][0]["run"] = "make\nmake lint\n".into()
+        })
+        .unwrap();
+        assert!(edited.contains("      - run: >\n          echo folded\n          line\n"));
+    }
+
+    #[test]
+    fn rewrites_flow_maps_as_a_whole() {
+        let content = "\
+name: CI
+jobs:
+  build:
+    steps:
+      - uses: actions/setup-node@v4
+        with: {node-version: 18, cache: npm} # lts
+      - run: npm test
+";
+        let edited = edited(content, |v| {
+            v["jobs"]["build"]["steps"][0]["with"]["node-version"] = 20.into()
+        })
+        .unwrap();
+
+        assert!(edited.contains("      - run: npm test\n"));
+        assert!(edited.contains("# lts"));
+    }
+
+    #[test]
+    fn leaves_anchors_and_aliases_alone() {
+        let content = "\
+name: CI
+jobs:
+  a:
+    runs-on: ubuntu-latest
+    defaults: &defaults
+      run:
+        shell: bash
+  b:
+    runs-on: ubuntu-latest
+    defaults: *defaults
+";
+        let change =
+            |v: &mut Value| v["jobs"]["a"]["defaults"]["run"]["working-directory"] = "app".into();
+        assert_eq!(edited(content, change), None);
+
+        let change = |v: &mut Value| v["jobs"]["b"]["defaults"]["run"]["shell"] = "sh".into();
+        assert_eq!(edited(content, change), None);
+
+        // the rest of the document can still be edited
+        let renamed = edited(content, |v| v["name"] = "app / CI".into()).unwrap();
+        assert_eq!(renamed, content.replace("name: CI", "name: app / CI"));
+    }
+
+    #[test]
+    fn leaves_merged_keys_alone() {
+        let content = "\
+x-job: &job
+  runs-on: ubuntu-latest
+jobs:
+  build:
+    <<: *job
+    steps:
+      - run: make
+";
+        let change = |v: &mut Value| v["jobs"]["build"]["<<"]["runs-on"] = "macos-latest".into();
+        assert_eq!(edited(content, change), None);
+
+        // keys next to the merge key can be added
+        let edited = edited(content, |v| {
+            v["jobs"]["build"]["timeout-minutes"] = 10.into()
+        })
+        .unwrap();
+        assert!(edited.contains("    <<: *job\n"));
+    }
+}
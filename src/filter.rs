@@ -0,0 +1,178 @@
+//! Workflows left out of a workspace: its `.hawkignore` file and `include` / `exclude` globs.
+//!
+//! ```yaml
+//! workspaces:
+//!   - name: my-app
+//!     path: ./packages/my-app/workflows
+//!     include: ["*.yml", "release/**"]
+//!     exclude: ["*.draft.yml"]
+//! ```
+//!
+//! Paths are relative to the workspace directory, shared templates are matched by filename.
+//! `*` stops at `/`: `*.yml` only matches the top level, `**/*.yml` every directory.
+//!
+//! A workflow is left out when it isn't included, is excluded or is ignored by `.hawkignore`,
+//! whatever the order: a `!pattern` of `.hawkignore` cannot bring back an excluded workflow.
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+use crate::log;
+use crate::models::workspace::Workspace;
+use crate::utils;
+
+/// Gitignore syntax, in the workspace directory
+pub const IGNORE_FILE: &str = ".hawkignore";
+
+pub struct Filter<'a> {
+    workspace: &'a Workspace,
+    ignore: Gitignore,
+    /// Every workflow is included when `None`
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl<'a> Filter<'a> {
+    pub fn new(workspace: &'a Workspace) -> Filter<'a> {
+        let mut builder = GitignoreBuilder::new(&workspace.path);
+        let ignore_file = Path::new(&workspace.path).join(IGNORE_FILE);
+
+        if ignore_file.exists() {
+            if let Some(err) = builder.add(&ignore_file) {
+                log::warn(&format!("{}: {}", utils::normalize_path(&ignore_file), err));
+            }
+        }
+
+        let ignore = builder.build().unwrap_or_else(|err| {
+            log::warn(&format!("{}: {}", utils::normalize_path(&ignore_file), err));
+            Gitignore::empty()
+        });
+
+        Filter {
+            workspace,
+            ignore,
+            include: (!workspace.include.is_empty()).then(|| globs(&workspace.include)),
+            exclude: globs(&workspace.exclude),
+        }
+    }
+
+    /// Whether the source is left out of the workspace. The `.hawkignore` file always is.
+    pub fn is_ignored(&self, source: &Path) -> bool {
+        let relative = utils::relative_source(source, self.workspace);
+
+        if relative == Path::new(IGNORE_FILE) {
+            return true;
+        }
+
+        let is_included = self.include.as_ref().is_none_or(|i| i.is_match(&relative));
+
+        !is_included
+            || self.exclude.is_match(&relative)
+            || self
+                .ignore
+                .matched_path_or_any_parents(&relative, false)
+                .is_ignore()
+    }
+}
+
+/// Compiles the globs, invalid ones are reported and left out.
+fn globs(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        match GlobBuilder::new(pattern).literal_separator(true).build() {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(err) => log::warn(&err.to_string()),
+        }
+    }
+
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn workspace(dir: &Path, include: &[&str], exclude: &[&str], ignore: &str) -> Workspace {
+        if !ignore.is_empty() {
+            fs::write(dir.join(IGNORE_FILE), ignore).unwrap();
+        }
+
+        Workspace {
+            name: "my-app".into(),
+            path: utils::normalize_path(dir),
+            include: include.iter().map(|p| p.to_string()).collect(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn is_ignored(workspace: &Workspace, relative: &str) -> bool {
+        Filter::new(workspace).is_ignored(&Path::new(&workspace.path).join(relative))
+    }
+
+    #[test]
+    fn keeps_everything_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace(dir.path(), &[], &[], "");
+
+        assert!(!is_ignored(&workspace, "deploy.yml"));
+        assert!(!is_ignored(&workspace, "release/publish.yml"));
+        assert!(is_ignored(&workspace, IGNORE_FILE));
+    }
+
+    #[test]
+    fn includes_matching_workflows() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace(dir.path(), &["*.yml", "release/**"], &[], "");
+
+        assert!(!is_ignored(&workspace, "deploy.yml"));
+        assert!(!is_ignored(&workspace, "release/publish.yml"));
+        assert!(!is_ignored(&workspace, "release/nightly/publish.yml"));
+        assert!(is_ignored(&workspace, "deploy.yaml"));
+        assert!(is_ignored(&workspace, "drafts/deploy.yml"));
+    }
+
+    #[test]
+    fn star_stops_at_separators() {
+        let dir = tempfile::tempdir().unwrap();
+        let top = workspace(dir.path(), &[], &["*.draft.yml"], "");
+
+        assert!(is_ignored(&top, "deploy.draft.yml"));
+        assert!(!is_ignored(&top, "release/publish.draft.yml"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let all = workspace(dir.path(), &[], &["**/*.draft.yml"], "");
+
+        assert!(is_ignored(&all, "deploy.draft.yml"));
+        assert!(is_ignored(&all, "release/publish.draft.yml"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace(dir.path(), &["*.yml"], &["lint.yml"], "");
+
+        assert!(is_ignored(&workspace, "lint.yml"));
+        assert!(!is_ignored(&workspace, "deploy.yml"));
+    }
+
+    #[test]
+    fn hawkignore_applies_on_top_of_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = workspace(
+            dir.path(),
+            &["*.yml", "release/**"],
+            &["deploy.yml"],
+            "release/\n!deploy.yml\n",
+        );
+
+        assert!(is_ignored(&workspace, "release/publish.yml"));
+        // a negated pattern doesn't bring back an excluded workflow
+        assert!(is_ignored(&workspace, "deploy.yml"));
+        assert!(!is_ignored(&workspace, "lint.yml"));
+    }
+}
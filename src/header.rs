@@ -0,0 +1,96 @@
+//! Provenance comment prepended to generated workflows.
+//!
+//! ```yaml
+//! # generated by hawk from packages/my-app/workflows/deploy.yml — do not edit
+//! # hawk-workspace: my-app
+//! # hawk-source: packages/my-app/workflows/deploy.yml
+//! # hawk-hash: 9264ee3553c8441f25c6b047728a4f9d2662fe91036077a0ee5dc71112d451da
+//! ```
+//!
+//! The message is configurable through `Config.header`, the `hawk-*` lines are always written
+//! and let hawk recognise the files it generated.
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_MESSAGE: &str = "generated by hawk from {source} — do not edit";
+
+const WORKSPACE_KEY: &str = "# hawk-workspace:";
+const SOURCE_KEY: &str = "# hawk-source:";
+const HASH_KEY: &str = "# hawk-hash:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub workspace: String,
+    pub source: String,
+    /// sha256 of the source file
+    pub hash: String,
+}
+
+impl Provenance {
+    /// Renders the header. `{source}`, `{hash}` and `{workspace}` are replaced in the message.
+    pub fn render(&self, message: &str) -> String {
+        let message = message
+            .replace("{source}", &self.source)
+            .replace("{hash}", &self.hash)
+            .replace("{workspace}", &self.workspace);
+
+        let mut header = String::new();
+
+        for line in message.lines() {
+            header.push_str(format!("# {}", line).trim_end());
+            header.push('\n');
+        }
+
+        header.push_str(&format!("{} {}\n", WORKSPACE_KEY, self.workspace));
+        header.push_str(&format!("{} {}\n", SOURCE_KEY, self.source));
+        header.push_str(&format!("{} {}\n", HASH_KEY, self.hash));
+
+        header
+    }
+
+    /// Reads the provenance from the leading comments of a generated file.
+    pub fn parse(content: &str) -> Option<Provenance> {
+        let mut workspace = None;
+        let mut source = None;
+        let mut hash = None;
+
+        for line in content.lines().take_while(|l| l.starts_with('#')) {
+            if let Some(value) = line.strip_prefix(WORKSPACE_KEY) {
+                workspace = Some(value.trim().to_string());
+            } else if let Some(value) = line.strip_prefix(SOURCE_KEY) {
+                source = Some(value.trim().to_string());
+            } else if let Some(value) = line.strip_prefix(HASH_KEY) {
+                hash = Some(value.trim().to_string());
+            }
+        }
+
+        Some(Provenance {
+            workspace: workspace?,
+            source: source?,
+            hash: hash?,
+        })
+    }
+
+    pub fn read(path: &Path) -> Option<Provenance> {
+        Provenance::parse(&fs::read_to_string(path).ok()?)
+    }
+}
+
+/// Removes the header written by hawk, if any.
+pub fn strip(content: &str) -> &str {
+    if Provenance::parse(content).is_none() {
+        return content;
+    }
+
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        offset += line.len();
+
+        if line.starts_with(HASH_KEY) {
+            break;
+        }
+    }
+
+    &content[offset..]
+}
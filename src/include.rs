@@ -0,0 +1,321 @@
+//! `!include` tags, expanded before a workflow is parsed.
+//!
+//! ```yaml
+//! env: !include shared/env.yml
+//! jobs:
+//!   <<: !include shared/jobs.yml
+//!   build:
+//!     steps:
+//!       - !include shared/setup-node.yml
+//!       - run: pnpm build
+//! ```
+//!
+//! Paths are relative to the config file. An included list in a list is spliced into it, an
+//! included map under `<<` is merged into the parent map, whose own keys take precedence.
+//! Fragments can include other fragments and use template variables.
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::models::config::Config;
+use crate::models::workspace::Workspace;
+use crate::template;
+use crate::template::TemplateError;
+use crate::utils;
+
+pub type Result<T> = std::result::Result<T, IncludeError>;
+
+#[derive(Debug)]
+pub enum IncludeError {
+    /// The fragment doesn't exist or cannot be read
+    NotFound {
+        source: String,
+        path: String,
+    },
+    /// A fragment including itself, the chain of includes leading back to it
+    Cycle(Vec<String>),
+    Invalid {
+        source: String,
+        reason: String,
+    },
+    Template(TemplateError),
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IncludeError::NotFound { source, path } => {
+                write!(f, "{}: cannot include {}, file not found", source, path)
+            }
+            IncludeError::Cycle(chain) => write!(f, "include cycle: {}", chain.join(" -> ")),
+            IncludeError::Invalid { source, reason } => write!(f, "{}: {}", source, reason),
+            IncludeError::Template(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<IncludeError> for std::io::Error {
+    fn from(e: IncludeError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    }
+}
+
+const TAG: &str = "!include";
+const MERGE_KEY: &str = "<<";
+
+/// Expands includes of a source workflow.
+pub struct Includes<'a> {
+    workspace: &'a Workspace,
+    config: &'a Config,
+    /// Files being expanded, to detect cycles
+    stack: Vec<PathBuf>,
+    /// Every fragment included, directly or not
+    pub files: Vec<PathBuf>,
+}
+
+impl<'a> Includes<'a> {
+    pub fn new(source: &Path, workspace: &'a Workspace, config: &'a Config) -> Includes<'a> {
+        Includes {
+            workspace,
+            config,
+            stack: vec![fs::canonicalize(source).unwrap_or_else(|_| source.into())],
+            files: Vec::new(),
+        }
+    }
+
+    /// Replaces the `!include` tags of the value. Returns whether there was any.
+    pub fn expand(&mut self, value: &mut Value) -> Result<bool> {
+        let count = self.files.len();
+
+        *value = self.expand_value(value.clone())?;
+
+        Ok(self.files.len() > count)
+    }
+
+    fn expand_value(&mut self, value: Value) -> Result<Value> {
+        match value {
+            Value::Tagged(tagged) if tagged.tag == TAG => self.include(&tagged.value),
+            Value::Sequence(items) => {
+                let mut expanded = Vec::with_capacity(items.len());
+
+                for item in items {
+                    match item {
+                        Value::Tagged(tagged) if tagged.tag == TAG => {
+                            match self.include(&tagged.value)? {
+                                Value::Sequence(included) => expanded.extend(included),
+                                included => expanded.push(included),
+                            }
+                        }
+                        item => expanded.push(self.expand_value(item)?),
+                    }
+                }
+
+                Ok(Value::Sequence(expanded))
+            }
+            Value::Mapping(mapping) => {
+                let mut expanded = Mapping::with_capacity(mapping.len());
+                let mut merged = Vec::new();
+
+                for (key, value) in mapping {
+                    match value {
+                        Value::Tagged(tagged) if key == MERGE_KEY && tagged.tag == TAG => {
+                            merged.push(self.include(&tagged.value)?)
+                        }
+                        value => {
+                            expanded.insert(key, self.expand_value(value)?);
+                        }
+                    }
+                }
+
+                for included in merged {
+                    let included = match included {
+                        Value::Mapping(included) => included,
+                        _ => return Err(self.invalid("only a map can be merged with `<<`")),
+                    };
+
+                    for (key, value) in included {
+                        if !expanded.contains_key(&key) {
+                            expanded.insert(key, value);
+                        }
+                    }
+                }
+
+                Ok(Value::Mapping(expanded))
+            }
+            value => Ok(value),
+        }
+    }
+
+    fn include(&mut self, path: &Value) -> Result<Value> {
+        let path = match path.as_str() {
+            Some(path) => path,
+            None => return Err(self.invalid("`!include` expects a path")),
+        };
+
+        let file = self.config.dir.join(path);
+        let canonical = fs::canonicalize(&file).map_err(|_| IncludeError::NotFound {
+            source: self.current(),
+            path: path.into(),
+        })?;
+
+        if self.stack.contains(&canonical) {
+            let mut chain: Vec<String> = self.stack.iter().map(|f| display(f)).collect();
+            chain.push(display(&canonical));
+
+            return Err(IncludeError::Cycle(chain));
+        }
+
+        if !self.files.contains(&file) {
+            self.files.push(file.clone());
+        }
+
+        let content = fs::read_to_string(&file).map_err(|_| IncludeError::NotFound {
+            source: self.current(),
+            path: path.into(),
+        })?;
+        let content = template::render(&content, &file, self.workspace, self.config)
+            .map_err(IncludeError::Template)?;
+        let value: Value = serde_yaml::from_str(&content).map_err(|e| IncludeError::Invalid {
+            source: utils::normalize_path(&file),
+            reason: e.to_string(),
+        })?;
+
+        self.stack.push(canonical);
+        let value = self.expand_value(value);
+        self.stack.pop();
+
+        value
+    }
+
+    fn current(&self) -> String {
+        self.stack.last().map(|f| display(f)).unwrap_or_default()
+    }
+
+    fn invalid(&self, reason: &str) -> IncludeError {
+        IncludeError::Invalid {
+            source: self.current(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Path relative to the working directory when possible
+fn display(path: &Path) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+
+    utils::normalize_path(path.strip_prefix(&cwd).unwrap_or(path))
+}
+
+/// Fragments included by the source workflow, directly or not.
+pub fn dependencies(source: &Path, workspace: &Workspace, config: &Config) -> Vec<PathBuf> {
+    let mut includes = Includes::new(source, workspace, config);
+
+    let content = match fs::read_to_string(source) {
+        Ok(content) => content,
+        Err(_) => return Vec::new(),
+    };
+
+    let rendered = template::render(&content, source, workspace, config);
+
+    if let Some(mut value) = rendered
+        .ok()
+        .and_then(|c| serde_yaml::from_str::<Value>(&c).ok())
+    {
+        includes.expand(&mut value).ok();
+    }
+
+    includes.files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expands the source with the given fragments, relative to the config directory
+    fn expand(source: &str, fragments: &[(&str, &str)]) -> Result<Value> {
+        let dir = tempfile::tempdir().unwrap();
+
+        for (path, content) in fragments {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let path = dir.path().join("ci.yml");
+        fs::write(&path, source).unwrap();
+
+        let workspace = Workspace::default();
+        let mut config = Config::new(".github/workflows");
+        config.dir = dir.path().into();
+
+        let mut value: Value = serde_yaml::from_str(source).unwrap();
+        Includes::new(&path, &workspace, &config).expand(&mut value)?;
+
+        Ok(value)
+    }
+
+    #[test]
+    fn splices_included_lists() {
+        let value = expand(
+            "steps:\n  - run: lint\n  - !include shared/setup.yml\n  - run: build\n",
+            &[(
+                "shared/setup.yml",
+                "- uses: actions/checkout@v4\n- run: pnpm install\n",
+            )],
+        )
+        .unwrap();
+
+        let steps: Vec<Value> = serde_yaml::from_str(
+            "- run: lint\n- uses: actions/checkout@v4\n- run: pnpm install\n- run: build\n",
+        )
+        .unwrap();
+        assert_eq!(value["steps"], Value::Sequence(steps));
+    }
+
+    #[test]
+    fn merged_maps_give_way_to_their_parent() {
+        let value = expand(
+            "env:\n  <<: !include shared/env.yml\n  REGION: eu-west-1\n",
+            &[("shared/env.yml", "REGION: us-east-1\nNODE: 20\n")],
+        )
+        .unwrap();
+
+        assert_eq!(value["env"]["REGION"], "eu-west-1");
+        assert_eq!(value["env"]["NODE"], 20);
+        assert_eq!(value["env"].get(MERGE_KEY), None);
+    }
+
+    #[test]
+    fn reports_include_cycles() {
+        let err = expand(
+            "jobs: !include a.yml\n",
+            &[
+                ("a.yml", "build: !include b.yml\n"),
+                ("b.yml", "test: !include a.yml\n"),
+            ],
+        )
+        .unwrap_err();
+
+        let chain = match err {
+            IncludeError::Cycle(chain) => chain,
+            err => panic!("expected a cycle, got {:?}", err),
+        };
+        let names: Vec<&str> = chain
+            .iter()
+            .map(|f| f.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(names, ["ci.yml", "a.yml", "b.yml", "a.yml"]);
+    }
+
+    #[test]
+    fn reports_missing_fragments() {
+        let err = expand("env: !include shared/missing.yml\n", &[]).unwrap_err();
+
+        assert!(matches!(
+            err,
+            IncludeError::NotFound { ref source, ref path }
+                if path == "shared/missing.yml" && source.ends_with("ci.yml")
+        ));
+    }
+}
@@ -1,6 +1,18 @@
 pub mod actions;
+pub mod aggregate;
+pub mod anchors;
 pub mod cli;
+pub mod diff;
+pub mod edit;
+pub mod filter;
+pub mod header;
+pub mod include;
 pub mod log;
 pub mod models;
+pub mod naming;
+pub mod plan;
+pub mod sync;
+pub mod template;
+pub mod transforms;
 pub mod utils;
 pub mod watchers;
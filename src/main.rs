@@ -4,7 +4,7 @@ use hawk_cli::cli::{Action, Args};
 use hawk_cli::log;
 use hawk_cli::models::config::Config;
 use hawk_cli::models::environment_files::is_empty_dir;
-use hawk_cli::models::files::File;
+use hawk_cli::plan::NameIndex;
 use hawk_cli::watchers;
 
 use clap::Parser;
@@ -12,7 +12,7 @@ use colored::*;
 use std::path::Path;
 
 fn main() -> notify::Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
     let config_file = match args.config {
         Some(c) => c,
         None => "hawk-config.yaml".into(),
@@ -36,7 +36,7 @@ fn main() -> notify::Result<()> {
             }
         }
         Some(Action::Clean) => {
-            let config: Config = Config::load(path).expect("Could not read config file");
+            let config = Config::read(path).expect("Could not read config file");
 
             let target = Path::new(&config.target);
 
@@ -50,18 +50,50 @@ fn main() -> notify::Result<()> {
                 return Ok(());
             }
 
-            for workspace in config.workspaces {
+            let mut conflicts = 0;
+            let mut errors = 0;
+
+            for workspace in config.workspaces.clone() {
                 if let Some(scope) = &args.scope {
                     if scope != &workspace.name {
                         continue;
                     }
                 }
 
-                actions::clean(workspace, &config.target)?;
+                if args.preview.is_verbose() {
+                    println!(
+                        "{} {}",
+                        workspace.name.bold().yellow(),
+                        format!("({})", workspace.path).dimmed()
+                    );
+                }
+
+                conflicts += counted(
+                    actions::clean(&workspace, &config, &args.preview, &args.conflicts),
+                    &workspace.name,
+                    &mut errors,
+                );
+            }
+
+            if args.scope.is_none() {
+                conflicts += counted(
+                    actions::clean_aggregate(&config, &args.preview, &args.conflicts),
+                    "aggregate",
+                    &mut errors,
+                );
+                conflicts += counted(
+                    actions::clean_removed(&config, &args.preview, &args.conflicts),
+                    "removed workspaces",
+                    &mut errors,
+                );
+            }
+
+            if conflicts > 0 || errors > 0 {
+                std::process::exit(1);
             }
         }
         Some(Action::List) => {
-            let config: Config = Config::load(path).expect("Could not read config file");
+            let config = Config::read(path).expect("Could not read config file");
             let target = &config.target;
 
             let p = Path::new(target);
@@ -86,8 +118,73 @@ fn main() -> notify::Result<()> {
                 actions::list(workspace, target);
             }
         }
+        Some(Action::Check) => {
+            let config = Config::read(path).expect("Could not read config file");
+
+            if !actions::validate(&config) {
+                std::process::exit(1);
+            }
+
+            let mut out_of_sync = 0;
+            let mut errors = 0;
+            let index = NameIndex::new(&config);
+
+            for workspace in &config.workspaces {
+                if let Some(scope) = &args.scope {
+                    if scope != &workspace.name {
+                        continue;
+                    }
+                }
+
+                println!(
+                    "{} {}",
+                    workspace.name.bold().yellow(),
+                    format!("({})", workspace.path).dimmed()
+                );
+
+                actions::warn_ambiguities(workspace, &config, &index);
+                out_of_sync += counted(
+                    actions::check(workspace, &config),
+                    &workspace.name,
+                    &mut errors,
+                );
+            }
+
+            if args.scope.is_none() {
+                out_of_sync += counted(actions::check_aggregate(&config), "aggregate", &mut errors);
+                out_of_sync += counted(
+                    actions::check_removed(&config),
+                    "removed workspaces",
+                    &mut errors,
+                );
+            }
+
+            if out_of_sync > 0 {
+                println!();
+                println!(
+                    "{} generated files out of sync. Run {} to update them.",
+                    out_of_sync.to_string().red().bold(),
+                    "hawk copy".underline()
+                );
+
+                std::process::exit(1);
+            }
+
+            if errors > 0 {
+                std::process::exit(1);
+            }
+        }
         _ => {
-            let config: Config = Config::load(path).expect("Could not read config file");
+            let config = Config::read(path).expect("Could not read config file");
+
+            if !actions::validate(&config) {
+                std::process::exit(1);
+            }
+
+            if args.watch && args.preview.dry_run {
+                log::warn("--watch is ignored in --dry-run mode");
+                args.watch = false;
+            }
 
             let handle = std::thread::spawn(move || {
                 if !args.watch {
@@ -100,15 +197,17 @@ fn main() -> notify::Result<()> {
             });
 
             let mut is_first = true;
-            for workspace in config.workspaces {
+            let mut conflicts = 0;
+            let mut errors = 0;
+            let index = NameIndex::new(&config);
+
+            for workspace in config.workspaces.clone() {
                 if let Some(scope) = &args.scope {
                     if scope != &workspace.name {
                         continue;
                     }
                 }
 
-                let target = config.target.clone();
-
                 if !is_first {
                     println!(); // spacer
                 } else {
@@ -129,20 +228,63 @@ fn main() -> notify::Result<()> {
                     )
                 }
 
-                actions::copy(&workspace, &target)?;
+                actions::warn_ambiguities(&workspace, &config, &index);
+                conflicts += counted(
+                    actions::copy(&workspace, &config, &args.preview, &args.conflicts),
+                    &workspace.name,
+                    &mut errors,
+                );
 
                 if args.watch {
+                    let config = config.clone();
+                    let preview = args.preview.clone();
+                    let conflict_flags = args.conflicts.clone();
+
                     std::thread::spawn(move || {
-                        if let Err(err) = watchers::watch_sync(workspace, &target) {
+                        let result =
+                            watchers::watch_sync(workspace, &config, &preview, &conflict_flags);
+
+                        if let Err(err) = result {
                             log::error("Something went wrong:", err)
                         }
                     });
                 }
             }
 
+            if args.scope.is_none() {
+                conflicts += counted(
+                    actions::aggregate(&config, &args.preview, &args.conflicts),
+                    "aggregate",
+                    &mut errors,
+                );
+                conflicts += counted(
+                    actions::clean_removed(&config, &args.preview, &args.conflicts),
+                    "removed workspaces",
+                    &mut errors,
+                );
+            }
+
+            if (conflicts > 0 || errors > 0) && !args.watch {
+                std::process::exit(1);
+            }
+
             handle.join().unwrap();
         }
     }
 
     Ok(())
 }
+
+/// Count returned by an action. Its error is reported and counted instead, the other
+/// workspaces still go through and hawk exits with a non-zero code at the end.
+fn counted(result: std::io::Result<usize>, name: &str, errors: &mut usize) -> usize {
+    match result {
+        Ok(count) => count,
+        Err(err) => {
+            log::error(&format!("{}:", name), err);
+            *errors += 1;
+
+            0
+        }
+    }
+}
@@ -1,12 +1,19 @@
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
 use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use crate::header;
 use crate::models::environment_files::{list_dirs, search_file, PackageJson, PnpmWorkspace};
 use crate::models::files;
 use crate::models::files::File;
+use crate::models::templates::{Aggregate, Templates};
+use crate::models::transforms::ConcurrencyOptions;
 use crate::models::workspace;
+use crate::naming;
+use crate::naming::Slug;
 
 pub type Result<T> = std::result::Result<T, ConfigError>;
 
@@ -35,6 +42,39 @@ impl fmt::Display for ConfigError {
 pub struct Config {
     pub workspaces: Vec<workspace::Workspace>,
     pub target: String,
+
+    /// Comment prepended to generated files, `{source}`, `{hash}` and `{workspace}` are replaced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+
+    /// Generated filenames, `{workspace}`, `{file}` and `{ext}` are replaced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub naming: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Slug::is_default")]
+    pub slug: Slug,
+
+    /// Adds a `concurrency` block to generated workflows without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<ConcurrencyOptions>,
+
+    /// `{{ vars.* }}` values available in every workspace.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub vars: IndexMap<String, Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub templates: Option<Templates>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregate: Option<Aggregate>,
+
+    /// Writes generated workflows with anchors, aliases and merge keys resolved.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub expand_anchors: bool,
+
+    /// Directory of the config file, `!include` paths are relative to it.
+    #[serde(skip)]
+    pub dir: PathBuf,
 }
 
 impl Config {
@@ -66,6 +106,15 @@ impl Config {
         Config {
             target: target.into(),
             workspaces: Vec::new(),
+            header: None,
+            naming: None,
+            slug: Slug::default(),
+            concurrency: None,
+            vars: IndexMap::new(),
+            templates: None,
+            aggregate: None,
+            expand_anchors: false,
+            dir: PathBuf::new(),
         }
     }
 
@@ -76,9 +125,9 @@ impl Config {
         let package_json_path = search_file(".", "package.json");
         let pnpm_workspace_path = search_file(".", "pnpm-workspace.yaml");
 
-        if pnpm_workspace_path.is_some() {
+        if let Some(pnpm_workspace_path) = pnpm_workspace_path {
             let mut workspaces: Vec<workspace::Workspace> = Vec::new();
-            let pnpm_workspace: PnpmWorkspace = PnpmWorkspace::load(&pnpm_workspace_path.unwrap())?;
+            let pnpm_workspace: PnpmWorkspace = PnpmWorkspace::load(&pnpm_workspace_path)?;
 
             for el in pnpm_workspace.packages {
                 add_workspaces(
@@ -130,6 +179,8 @@ fn add_workspaces(
                 name: pkg.name,
                 package_json: Some(dir.to_str().unwrap().into()),
                 path: dir.join(workflows_dir).to_str().unwrap_or_default().into(),
+                root: Some(dir.to_str().unwrap().into()),
+                ..Default::default()
             };
 
             workspaces.push(wk);
@@ -138,6 +189,8 @@ fn add_workspaces(
                 name: dir.file_stem().unwrap().to_str().unwrap().into(),
                 package_json: None,
                 path: dir.join(workflows_dir).to_str().unwrap_or_default().into(),
+                root: Some(dir.to_str().unwrap().into()),
+                ..Default::default()
             });
         }
     }
@@ -145,4 +198,22 @@ fn add_workspaces(
     Ok(())
 }
 
+impl Config {
+    /// Loads the config file, keeping track of its directory.
+    pub fn read(path: &Path) -> files::Result<Config> {
+        let mut config = Config::load(path)?;
+        config.dir = path.parent().unwrap_or_else(|| Path::new("")).into();
+
+        Ok(config)
+    }
+
+    pub fn header(&self) -> &str {
+        self.header.as_deref().unwrap_or(header::DEFAULT_MESSAGE)
+    }
+
+    pub fn naming(&self) -> &str {
+        self.naming.as_deref().unwrap_or(naming::DEFAULT_TEMPLATE)
+    }
+}
+
 impl File<Config> for Config {}
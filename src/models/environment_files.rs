@@ -54,7 +54,7 @@ pub fn search_file(search_path: &str, filename: &str) -> Option<PathBuf> {
 pub fn is_empty_dir(path: &Path) -> bool {
     let count = WalkDir::new(path)
         .into_iter()
-        .filter(|r| r.as_ref().map_or(false, |e| is_workflow_file(e.path())))
+        .filter(|r| r.as_ref().is_ok_and(|e| is_workflow_file(e.path())))
         .count();
 
     count == 0
@@ -63,10 +63,7 @@ pub fn is_empty_dir(path: &Path) -> bool {
 pub fn list_dirs(path: &Path) -> Vec<PathBuf> {
     WalkDir::new(path)
         .into_iter()
-        .filter_map(move |p| match p {
-            Ok(entry) => Some(entry),
-            _ => None,
-        })
+        .filter_map(|p| p.ok())
         .filter(|p| p.path().is_dir())
         .map(|f| PathBuf::from(f.path()))
         .collect()
@@ -75,10 +72,7 @@ pub fn list_dirs(path: &Path) -> Vec<PathBuf> {
 pub fn list_files(path: &Path) -> Vec<PathBuf> {
     WalkDir::new(path)
         .into_iter()
-        .filter_map(move |p| match p {
-            Ok(entry) => Some(entry),
-            _ => None,
-        })
+        .filter_map(|p| p.ok())
         .filter(|p| p.path().is_file())
         .map(|f| PathBuf::from(f.path()))
         .collect()
@@ -1,14 +1,14 @@
 use serde::{de, Serialize};
+use std::io::Write;
 use std::{fmt, fs, io, path::Path};
 
 pub type Result<T> = std::result::Result<T, FileError>;
 
-#[derive(Debug, Clone)]
-struct PackageJsonError;
-
 #[derive(Debug, Clone)]
 pub enum FileKind {
     JSON,
+    JSON5,
+    TOML,
     YAML,
 }
 
@@ -20,42 +20,49 @@ impl FileKind {
 
         match p.extension().unwrap().to_str().unwrap() {
             "json" => Ok(FileKind::JSON),
+            "json5" => Ok(FileKind::JSON5),
+            "toml" => Ok(FileKind::TOML),
             "yml" | "yaml" => Ok(FileKind::YAML),
             _ => Err(FileError::UnsupportedExtension),
         }
     }
+
+    pub fn parse<T: de::DeserializeOwned>(&self, content: &str) -> Result<T> {
+        Ok(match self {
+            FileKind::JSON => serde_json::from_str(content)?,
+            FileKind::JSON5 => json5::from_str(content)?,
+            FileKind::TOML => toml::from_str(content)?,
+            FileKind::YAML => serde_yaml::from_str(content)?,
+        })
+    }
 }
 
 pub trait File<T>
 where
     T: de::DeserializeOwned,
 {
-    /// Reads file from filesystem. It must be json or yaml.
+    /// Reads file from filesystem. It must be json, json5, toml or yaml.
     fn load(path: &Path) -> Result<T> {
-        let r = fs::File::open(path)?;
+        let content = fs::read_to_string(path)?;
         let kind = FileKind::from_path(path)?;
 
-        match kind {
-            FileKind::JSON => match serde_json::from_reader(r) {
-                Ok(d) => Ok(d),
-                Err(e) => Err(FileError::from(e)),
-            },
-            FileKind::YAML => match serde_yaml::from_reader(r) {
-                Ok(d) => Ok(d),
-                Err(e) => Err(FileError::from(e)),
-            },
-        }
+        kind.parse(&content)
     }
 
     fn write(&self, path: &Path) -> Result<()>
     where
         Self: Serialize,
     {
-        let r = fs::File::create(path)?;
+        let mut r = fs::File::create(path)?;
         let kind = FileKind::from_path(path)?;
 
         match kind {
-            FileKind::JSON => serde_json::to_writer_pretty(r, self)?,
+            // JSON is valid JSON5
+            FileKind::JSON | FileKind::JSON5 => serde_json::to_writer_pretty(r, self)?,
+            FileKind::TOML => {
+                let content = toml::to_string_pretty(self)?;
+                r.write_all(content.as_bytes())?;
+            }
             FileKind::YAML => serde_yaml::to_writer(r, self)?,
         }
 
@@ -69,6 +76,9 @@ pub enum FileError {
     UnsupportedExtension,
     InvalidYAMLSyntax(serde_yaml::Error),
     InvalidJSONSyntax(serde_json::Error),
+    InvalidJSON5Syntax(json5::Error),
+    InvalidTOMLSyntax(toml::de::Error),
+    TOMLSerialization(toml::ser::Error),
     IO(io::Error),
 }
 
@@ -77,10 +87,13 @@ impl fmt::Display for FileError {
         let message: String = match self {
             FileError::NotFound => "No such file or directory".into(),
             FileError::UnsupportedExtension => {
-                "unsupported file extension (allowed: yaml | yml | json)".into()
+                "unsupported file extension (allowed: yaml | yml | json | json5 | toml)".into()
             }
             FileError::InvalidYAMLSyntax(err) => format!("Invalid YAML syntax: {:?}", err),
             FileError::InvalidJSONSyntax(err) => format!("Invalid JSON syntax: {:?}", err),
+            FileError::InvalidJSON5Syntax(err) => format!("Invalid JSON5 syntax: {}", err),
+            FileError::InvalidTOMLSyntax(err) => format!("Invalid TOML syntax: {}", err),
+            FileError::TOMLSerialization(err) => format!("Cannot write TOML: {}", err),
             FileError::IO(err) => format!("{}", err),
         };
 
@@ -94,12 +107,40 @@ impl From<serde_json::Error> for FileError {
     }
 }
 
+impl From<json5::Error> for FileError {
+    fn from(e: json5::Error) -> Self {
+        FileError::InvalidJSON5Syntax(e)
+    }
+}
+
+impl From<toml::de::Error> for FileError {
+    fn from(e: toml::de::Error) -> Self {
+        FileError::InvalidTOMLSyntax(e)
+    }
+}
+
+impl From<toml::ser::Error> for FileError {
+    fn from(e: toml::ser::Error) -> Self {
+        FileError::TOMLSerialization(e)
+    }
+}
+
 impl From<serde_yaml::Error> for FileError {
     fn from(e: serde_yaml::Error) -> Self {
         FileError::InvalidYAMLSyntax(e)
     }
 }
 
+impl From<FileError> for io::Error {
+    fn from(e: FileError) -> Self {
+        match e {
+            FileError::IO(err) => err,
+            FileError::NotFound => io::Error::from(io::ErrorKind::NotFound),
+            err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        }
+    }
+}
+
 impl From<io::Error> for FileError {
     fn from(e: io::Error) -> Self {
         match e.kind() {
@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::models::files::{File, FileError};
+use crate::utils;
+
+pub type Result<T> = std::result::Result<T, ManifestError>;
+
+#[derive(Debug)]
+pub enum ManifestError {
+    /// The manifest exists but cannot be parsed, nothing is written until it's fixed
+    Invalid {
+        path: String,
+        reason: String,
+    },
+    File(FileError),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManifestError::Invalid { path, reason } => write!(
+                f,
+                "{} is not a valid manifest ({}), fix or delete it to run hawk again",
+                path, reason
+            ),
+            ManifestError::File(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<FileError> for ManifestError {
+    fn from(e: FileError) -> Self {
+        ManifestError::File(e)
+    }
+}
+
+impl From<io::Error> for ManifestError {
+    fn from(e: io::Error) -> Self {
+        ManifestError::File(FileError::from(e))
+    }
+}
+
+impl From<ManifestError> for io::Error {
+    fn from(e: ManifestError) -> Self {
+        match e {
+            ManifestError::File(err) => io::Error::from(err),
+            err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        }
+    }
+}
+
+/// Serializes manifest updates coming from different watcher threads.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Record of the files generated by hawk, stored in the target directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Generated files keyed by their name in the target directory
+    pub files: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub workspace: String,
+    pub source: String,
+    /// sha256 of the generated content
+    pub hash: String,
+}
+
+impl Entry {
+    pub fn new(workspace: &str, source: &Path, content: &[u8]) -> Entry {
+        Entry {
+            workspace: workspace.into(),
+            source: utils::normalize_path(source),
+            hash: utils::hash(content),
+        }
+    }
+}
+
+impl File<Manifest> for Manifest {}
+
+impl Manifest {
+    pub const FILENAME: &'static str = ".hawk-manifest.json";
+
+    pub fn path(target: &str) -> PathBuf {
+        Path::new(target).join(Manifest::FILENAME)
+    }
+
+    /// Reads the manifest of the target directory, empty if there's none yet. A manifest that
+    /// cannot be parsed is an error: treating it as empty would lose track of every file.
+    pub fn read(target: &str) -> Result<Manifest> {
+        let path = Manifest::path(target);
+
+        match Manifest::load(&path) {
+            Ok(manifest) => Ok(manifest),
+            Err(FileError::NotFound) => Ok(Manifest::default()),
+            Err(err @ FileError::IO(_)) => Err(err.into()),
+            Err(err) => Err(ManifestError::Invalid {
+                path: utils::normalize_path(&path),
+                reason: err.to_string(),
+            }),
+        }
+    }
+
+    /// Loads, updates and saves the manifest of the target directory.
+    pub fn update<F>(target: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Manifest),
+    {
+        let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut manifest = Manifest::read(target)?;
+
+        f(&mut manifest);
+
+        if manifest.files.is_empty() {
+            if Manifest::path(target).exists() {
+                std::fs::remove_file(Manifest::path(target))?;
+            }
+
+            return Ok(());
+        }
+
+        // written aside then renamed, a watcher thread never reads a partial manifest
+        let path = Manifest::path(target);
+        let partial = path.with_extension("json.partial");
+        let content = serde_json::to_string_pretty(&manifest).map_err(FileError::from)?;
+
+        std::fs::write(&partial, content)?;
+        std::fs::rename(&partial, &path)?;
+
+        Ok(())
+    }
+
+    /// Whether the file was changed since hawk generated it.
+    pub fn is_modified(&self, path: &Path) -> bool {
+        match (self.files.get(&utils::file_name(path)), std::fs::read(path)) {
+            (Some(entry), Ok(content)) => utils::hash(&content) != entry.hash,
+            _ => false,
+        }
+    }
+
+    /// Entries generated for the given workspace
+    pub fn workspace<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (&'a String, &'a Entry)> {
+        self.files.iter().filter(move |(_, e)| e.workspace == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn missing_manifest_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = Manifest::read(dir.path().to_str().unwrap()).unwrap();
+
+        assert!(manifest.files.is_empty());
+    }
+
+    #[test]
+    fn corrupt_manifest_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().to_str().unwrap();
+        let path = Manifest::path(target);
+        fs::write(&path, "<<<<<<< HEAD\n{}").unwrap();
+
+        let err = Manifest::read(target).unwrap_err();
+        assert!(matches!(err, ManifestError::Invalid { .. }));
+
+        let updated = Manifest::update(target, |m| m.files.clear());
+        assert!(updated.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<<<<<<< HEAD\n{}");
+    }
+}
@@ -1,5 +1,8 @@
 pub mod config;
 pub mod environment_files;
 pub mod files;
+pub mod manifest;
+pub mod templates;
+pub mod transforms;
 pub mod workflow;
 pub mod workspace;
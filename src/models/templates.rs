@@ -0,0 +1,119 @@
+use globset::Glob;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::path::Path;
+
+use crate::models::workspace::Workspace;
+use crate::utils;
+
+/// Shared workflows generated for every matching workspace.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Templates {
+    /// Directory of the shared workflows
+    pub path: String,
+
+    /// Globs of the workspace names to generate the templates for, checked when the config is
+    /// loaded
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "globs"
+    )]
+    pub workspaces: Vec<String>,
+
+    /// Workspaces having one of these tags get the templates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl Templates {
+    /// Whether the templates are generated for the workspace. Without `workspaces` nor `tags`
+    /// every workspace matches.
+    pub fn matches(&self, workspace: &Workspace) -> bool {
+        if self.workspaces.is_empty() && self.tags.is_empty() {
+            return true;
+        }
+
+        // the patterns are valid, see `globs`
+        let is_selected = self.workspaces.iter().any(|pattern| {
+            Glob::new(pattern).is_ok_and(|glob| glob.compile_matcher().is_match(&workspace.name))
+        });
+
+        is_selected || workspace.tags.iter().any(|t| self.tags.contains(t))
+    }
+}
+
+/// Rejects invalid globs, a typo would silently leave workspaces without their templates.
+fn globs<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let patterns = Vec::<String>::deserialize(deserializer)?;
+
+    for pattern in &patterns {
+        if let Err(err) = Glob::new(pattern) {
+            return Err(de::Error::custom(format!(
+                "invalid workspace glob {:?}: {}",
+                pattern,
+                err.kind()
+            )));
+        }
+    }
+
+    Ok(patterns)
+}
+
+/// Workflow generated once with a matrix over every workspace.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Aggregate {
+    /// Template workflow
+    pub source: String,
+
+    /// Name of the generated file, defaults to the source filename
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+
+    /// Jobs getting the matrix, all of them when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jobs: Vec<String>,
+}
+
+impl Aggregate {
+    pub fn filename(&self) -> String {
+        match &self.filename {
+            Some(filename) => filename.clone(),
+            None => utils::file_name(Path::new(&self.source)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str, tags: &[&str]) -> Workspace {
+        Workspace {
+            name: name.into(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_globs_and_tags() {
+        let templates: Templates =
+            serde_yaml::from_str("path: t\nworkspaces: ['@acme/*']\ntags: [node]").unwrap();
+
+        assert!(templates.matches(&workspace("@acme/web", &[])));
+        assert!(templates.matches(&workspace("api", &["node"])));
+        assert!(!templates.matches(&workspace("api", &["go"])));
+    }
+
+    #[test]
+    fn rejects_invalid_globs() {
+        let err = serde_yaml::from_str::<Templates>("path: t\nworkspaces: ['web-[']")
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("invalid workspace glob \"web-[\""), "{}", err);
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// Opt-in rewrites applied to the workflows of a workspace while they are copied.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Transforms {
+    /// Add a `paths` filter scoped to the workspace root to `push` and `pull_request` triggers
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub paths_filter: bool,
+    /// Set the workflow `defaults.run.working-directory` to the workspace root
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub working_directory: bool,
+    /// Check out only the workspace root and these shared paths in `actions/checkout` steps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sparse_checkout: Option<Vec<String>>,
+    /// Template of the workflow `name`, `{workspace}` and `{name}` are replaced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl Transforms {
+    pub fn is_empty(&self) -> bool {
+        !self.paths_filter
+            && !self.working_directory
+            && self.sparse_checkout.is_none()
+            && self.name.is_none()
+    }
+}
+
+pub const DEFAULT_CONCURRENCY_GROUP: &str = "{workspace}-{file}-${{ github.ref }}";
+
+/// `concurrency` block injected in generated workflows
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConcurrencyOptions {
+    /// `{workspace}` and `{file}` (source name without extension) are replaced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancel_in_progress: bool,
+}
+
+impl ConcurrencyOptions {
+    pub fn group(&self) -> &str {
+        self.group.as_deref().unwrap_or(DEFAULT_CONCURRENCY_GROUP)
+    }
+}
@@ -1,9 +1,591 @@
+//! Typed model of a GitHub Actions workflow.
+//!
+//! Every struct keeps the keys it doesn't know about in `extra`, so a workflow can be
+//! loaded, edited and written back without losing anything. Known keys are written in the
+//! order of the struct fields, followed by the unknown ones in their original order.
 use crate::models::files::*;
-use serde::Deserialize;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Deserialize)]
+pub type Env = IndexMap<String, Value>;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Workflow {
+    /// Optional in GitHub, which then displays the file path
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub name: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_name: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on: Option<On>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<Defaults>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<Concurrency>,
+
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub jobs: IndexMap<String, Job>,
+
+    /// Keys hawk doesn't need to understand, kept as they are.
+    #[serde(flatten)]
+    pub extra: Mapping,
 }
 
 impl File<Workflow> for Workflow {}
+
+impl FromStr for Workflow {
+    type Err = FileError;
+
+    fn from_str(s: &str) -> Result<Workflow> {
+        Ok(serde_yaml::from_str(s)?)
+    }
+}
+
+impl Workflow {
+    pub fn to_yaml(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+/// The `on` key. It can be a single event, a list of events or a map of configured events.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum On {
+    Event(String),
+    Events(Vec<String>),
+    Map(IndexMap<String, Option<Trigger>>),
+}
+
+impl On {
+    pub fn contains(&self, event: &str) -> bool {
+        match self {
+            On::Event(e) => e == event,
+            On::Events(events) => events.iter().any(|e| e == event),
+            On::Map(events) => events.contains_key(event),
+        }
+    }
+
+    /// Converts to the map form, the only one that can hold filters.
+    pub fn into_map(self) -> IndexMap<String, Option<Trigger>> {
+        match self {
+            On::Event(e) => IndexMap::from([(e, None)]),
+            On::Events(events) => events.into_iter().map(|e| (e, None)).collect(),
+            On::Map(events) => events,
+        }
+    }
+
+    /// Configuration of the given event, if it has one.
+    pub fn get(&self, event: &str) -> Option<&EventConfig> {
+        match self {
+            On::Map(events) => match events.get(event) {
+                Some(Some(Trigger::Config(config))) => Some(config),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, event: &str) -> Option<&mut EventConfig> {
+        match self {
+            On::Map(events) => match events.get_mut(event) {
+                Some(Some(Trigger::Config(config))) => Some(config),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Trigger {
+    Config(Box<EventConfig>),
+    Schedule(Vec<Cron>),
+    Other(Value),
+}
+
+/// Configuration of an event: activity types, filters and, for `workflow_dispatch`,
+/// `workflow_call` and `workflow_run`, their specific keys.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EventConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub types: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branches: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branches_ignore: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags_ignore: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paths: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paths_ignore: Option<Vec<String>>,
+
+    /// `workflow_run`: names of the workflows triggering this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workflows: Option<Vec<String>>,
+
+    /// `workflow_dispatch` and `workflow_call`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inputs: Option<IndexMap<String, Input>>,
+
+    /// `workflow_call`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<IndexMap<String, WorkflowOutput>>,
+
+    /// `workflow_call`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets: Option<IndexMap<String, Option<SecretInput>>>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Cron {
+    pub cron: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Input {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecation_message: Option<String>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct WorkflowOutput {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    pub value: String,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SecretInput {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+/// `read-all` / `write-all` or a map of scopes to `read` / `write` / `none`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Permissions {
+    All(String),
+    Scopes(IndexMap<String, String>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Defaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<RunDefaults>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<String>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Concurrency {
+    Group(String),
+    Config(ConcurrencyConfig),
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConcurrencyConfig {
+    pub group: String,
+
+    /// Boolean or expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancel_in_progress: Option<Value>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+/// A job either runs steps or calls a reusable workflow with `uses`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Job {
+    Reusable(Box<ReusableJob>),
+    Normal(Box<NormalJob>),
+}
+
+impl Job {
+    pub fn needs(&self) -> Option<&Needs> {
+        match self {
+            Job::Reusable(job) => job.needs.as_ref(),
+            Job::Normal(job) => job.needs.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Needs {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NormalJob {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub needs: Option<Needs>,
+
+    #[serde(default, rename = "if", skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Value>,
+
+    /// Label, list of labels or `group` / `labels` map
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runs_on: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<Concurrency>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<IndexMap<String, String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<Defaults>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_minutes: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continue_on_error: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<Strategy>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub services: Option<IndexMap<String, Container>>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Step>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReusableJob {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// `./.github/workflows/file.yml` or `owner/repo/.github/workflows/file.yml@ref`
+    pub uses: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub with: Option<IndexMap<String, Value>>,
+
+    /// `inherit` or a map of secrets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub needs: Option<Needs>,
+
+    #[serde(default, rename = "if", skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<Concurrency>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<Strategy>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Strategy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<Matrix>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fail_fast: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_parallel: Option<Value>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Matrix {
+    /// `${{ fromJSON(...) }}`
+    Expression(String),
+    Config(MatrixConfig),
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MatrixConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<Value>,
+
+    /// Matrix dimensions, lists of values or expressions
+    #[serde(flatten)]
+    pub dimensions: IndexMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Container {
+    Image(String),
+    Config(Box<ContainerConfig>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ContainerConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<IndexMap<String, String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ports: Option<Vec<Value>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Step {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(default, rename = "if", skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uses: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub with: Option<IndexMap<String, Value>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continue_on_error: Option<Value>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_minutes: Option<Value>,
+
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use walkdir::WalkDir;
+
+    /// Workflows of the example project and of the repository itself
+    fn fixtures() -> Vec<PathBuf> {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+
+        ["example", ".github/workflows"]
+            .iter()
+            .flat_map(|dir| WalkDir::new(root.join(dir)).sort_by_file_name())
+            .filter_map(|e| e.ok())
+            .map(|e| e.into_path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "yml"))
+            .collect()
+    }
+
+    fn round_trip(content: &str) -> String {
+        Workflow::from_str(content).unwrap().to_yaml().unwrap()
+    }
+
+    /// Keys of every map, depth first, in document order
+    fn keys(value: &Value, path: &str, keys: &mut Vec<String>) {
+        match value {
+            Value::Mapping(mapping) => {
+                for (key, value) in mapping {
+                    let path = format!("{}.{}", path, key.as_str().unwrap_or_default());
+                    keys.push(path.clone());
+                    self::keys(value, &path, keys);
+                }
+            }
+            Value::Sequence(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self::keys(item, &format!("{}.{}", path, i), keys);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn fixtures_round_trip() {
+        let fixtures = fixtures();
+        assert!(!fixtures.is_empty());
+
+        for fixture in fixtures {
+            let content = fs::read_to_string(&fixture).unwrap();
+            let serialized = round_trip(&content);
+
+            let original: Value = serde_yaml::from_str(&content).unwrap();
+            let written: Value = serde_yaml::from_str(&serialized).unwrap();
+
+            assert_eq!(original, written, "{}", fixture.display());
+            assert_eq!(round_trip(&serialized), serialized, "{}", fixture.display());
+        }
+    }
+
+    #[test]
+    fn unknown_keys_keep_their_order() {
+        let content = "\
+name: CI
+on: push
+x-zeta: 1
+x-alpha: 2
+jobs:
+  build:
+    runs-on: ubuntu-latest
+    x-job-b: b
+    x-job-a: a
+    steps:
+      - run: echo
+        x-step-b: b
+        x-step-a: a
+      - uses: actions/checkout@v4
+";
+        let original: Value = serde_yaml::from_str(content).unwrap();
+        let written: Value = serde_yaml::from_str(&round_trip(content)).unwrap();
+
+        assert_eq!(original, written);
+
+        let mut original_keys = Vec::new();
+        let mut written_keys = Vec::new();
+        keys(&original, "", &mut original_keys);
+        keys(&written, "", &mut written_keys);
+
+        // unknown keys follow the known ones of their map, in their original order
+        let unknown = |keys: &[String]| -> Vec<String> {
+            let mut unknown: Vec<String> =
+                keys.iter().filter(|k| k.contains(".x-")).cloned().collect();
+            unknown.sort_by_key(|k| k[..k.rfind('.').unwrap()].to_string());
+            unknown
+        };
+
+        assert_eq!(unknown(&original_keys), unknown(&written_keys));
+        assert_eq!(written_keys.last().map(String::as_str), Some(".x-alpha"));
+    }
+}
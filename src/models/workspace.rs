@@ -1,11 +1,16 @@
 use colored::*;
+use indexmap::IndexMap;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_yaml::Value;
 use std::fmt;
 use std::fs;
+use std::path::Path;
 
 use crate::log;
 use crate::models::environment_files::PackageJson;
+use crate::models::transforms::Transforms;
+use crate::utils;
 
 pub type Result<T> = std::result::Result<T, WorkspaceError>;
 
@@ -15,11 +20,34 @@ pub enum WorkspaceError {
     InvalidPath(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Workspace {
     pub name: String,
     pub path: String,
     pub package_json: Option<String>,
+
+    /// Workspace directory. Defaults to the `package_json` directory or the folder containing `path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+
+    /// Used to select shared templates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+
+    /// Globs of the workflows to generate, relative to `path`. All of them when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    /// Globs of the workflows not to generate, relative to `path`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Transforms::is_empty")]
+    pub transforms: Transforms,
+
+    /// `{{ vars.* }}` values of the workspace, taking precedence over the config ones.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub vars: IndexMap<String, Value>,
 }
 
 impl fmt::Display for WorkspaceError {
@@ -52,6 +80,47 @@ impl Workspace {
         Ok(())
     }
 
+    /// Workspace directory relative to the repository root, without leading `./` or trailing `/`.
+    pub fn root(&self) -> String {
+        if let Some(root) = &self.root {
+            return utils::normalize_path(Path::new(root));
+        }
+
+        if let Some(package_json) = &self.package_json {
+            let p = Path::new(package_json);
+
+            if p.is_file() || p.ends_with("package.json") {
+                return utils::normalize_path(p.parent().unwrap_or(p));
+            }
+
+            return utils::normalize_path(p);
+        }
+
+        // `packages/my-app/workflows` or `packages/my-app/.github/workflows`
+        let mut dir = Path::new(&self.path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+
+        if dir.ends_with(".github") {
+            dir = dir.parent().unwrap_or(dir);
+        }
+
+        utils::normalize_path(dir)
+    }
+
+    /// Content of the workspace package.json, if any.
+    pub fn package(&self) -> Option<serde_json::Value> {
+        let path = Path::new(self.package_json.as_ref()?);
+
+        let content = if path.is_dir() {
+            fs::read_to_string(path.join("package.json"))
+        } else {
+            fs::read_to_string(path)
+        };
+
+        serde_json::from_str(&content.ok()?).ok()
+    }
+
     pub fn load_name_if_possible(&mut self) -> serde_json::Result<()> {
         if let Some(path) = &self.package_json {
             let mut p: String = path.into();
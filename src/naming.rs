@@ -0,0 +1,166 @@
+//! Names of the generated files.
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const DEFAULT_TEMPLATE: &str = "{workspace}--{file}";
+
+/// How workspace and file names are turned into a flat filename
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Slug {
+    /// Keep letters (any script), digits, `.`, `_` and `-`, replace everything else with `-`
+    #[default]
+    Safe,
+    /// Same as `safe`, lowercased
+    Lowercase,
+}
+
+impl Slug {
+    pub fn is_default(&self) -> bool {
+        *self == Slug::default()
+    }
+
+    /// `@acme/web` -> `acme-web`, `My App` -> `My-App`, `café` -> `cafe`, `应用` -> `应用`.
+    /// Empty when the value has no letter or digit.
+    pub fn apply(&self, value: &str) -> String {
+        let mut slug = String::with_capacity(value.len());
+
+        for c in value.trim_start_matches('@').chars() {
+            let c = transliterate(c).unwrap_or(c);
+
+            if c.is_alphanumeric() || c == '.' || c == '_' || c == '-' {
+                slug.push(c);
+            } else if !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+
+        let slug = slug.trim_matches('-');
+
+        match self {
+            Slug::Safe => slug.into(),
+            Slug::Lowercase => slug.to_lowercase(),
+        }
+    }
+}
+
+/// Latin letters with diacritics to their ASCII base letter
+fn transliterate(c: char) -> Option<char> {
+    let ascii = match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' => 'A',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'È' | 'É' | 'Ê' | 'Ë' => 'E',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'Ì' | 'Í' | 'Î' | 'Ï' => 'I',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => 'o',
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' => 'O',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'Ù' | 'Ú' | 'Û' | 'Ü' => 'U',
+        'ñ' => 'n',
+        'Ñ' => 'N',
+        'ç' => 'c',
+        'Ç' => 'C',
+        'ý' | 'ÿ' => 'y',
+        'Ý' => 'Y',
+        _ => return None,
+    };
+
+    Some(ascii)
+}
+
+/// Source path relative to its directory without extension, subdirectories flattened:
+/// `release/publish.yml` -> `release-publish`
+pub fn file(source: &Path) -> String {
+    let stem = source.with_extension("");
+
+    stem.components()
+        .filter_map(|c| c.as_os_str().to_str())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+/// Renders the filename of a generated file. `{workspace}`, `{file}` (source path relative to
+/// the workspace directory, see [`file`]) and `{ext}` (`yml` or `yaml`) are replaced; the
+/// extension is appended when `{ext}` is missing.
+pub fn filename(template: &str, slug: Slug, workspace: &str, source: &Path) -> String {
+    let file = file(source);
+    // JSON and TOML sources are written as YAML
+    let ext = source
+        .extension()
+        .and_then(|s| s.to_str())
+        .filter(|ext| *ext == "yaml")
+        .unwrap_or("yml");
+
+    let mut name = template
+        .replace("{workspace}", &slug.apply(workspace))
+        .replace("{file}", &slug.apply(&file))
+        .replace("{ext}", ext)
+        .replace(['/', '\\'], "-");
+
+    if !template.contains("{ext}") {
+        name = format!("{}.{}", name, ext);
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_npm_scopes_and_symbols() {
+        assert_eq!(Slug::Safe.apply("@acme/web"), "acme-web");
+        assert_eq!(Slug::Safe.apply("My App"), "My-App");
+        assert_eq!(Slug::Lowercase.apply("My App"), "my-app");
+    }
+
+    #[test]
+    fn transliterates_latin_letters() {
+        assert_eq!(Slug::Safe.apply("café"), "cafe");
+        assert_eq!(Slug::Lowercase.apply("Ärger"), "arger");
+    }
+
+    #[test]
+    fn keeps_non_latin_letters() {
+        assert_eq!(Slug::Safe.apply("应用"), "应用");
+        assert_eq!(Slug::Safe.apply("@акме/веб"), "акме-веб");
+        assert_eq!(Slug::Lowercase.apply("Δέλτα"), "δέλτα");
+    }
+
+    #[test]
+    fn empty_without_letters_or_digits() {
+        assert_eq!(Slug::Safe.apply("@/"), "");
+        assert_eq!(Slug::Safe.apply("🚀"), "");
+    }
+
+    #[test]
+    fn flattens_subdirectories() {
+        let name = filename(
+            DEFAULT_TEMPLATE,
+            Slug::Safe,
+            "my-app",
+            Path::new("release/publish.yml"),
+        );
+
+        assert_eq!(name, "my-app--release-publish.yml");
+    }
+
+    #[test]
+    fn keeps_yaml_extension_and_writes_other_formats_as_yml() {
+        let name = |source: &str, template: &str| {
+            filename(template, Slug::Safe, "my-app", Path::new(source))
+        };
+
+        assert_eq!(name("ci.yaml", DEFAULT_TEMPLATE), "my-app--ci.yaml");
+        assert_eq!(name("ci.yml", DEFAULT_TEMPLATE), "my-app--ci.yml");
+        assert_eq!(name("ci.json", DEFAULT_TEMPLATE), "my-app--ci.yml");
+        assert_eq!(name("ci.json5", DEFAULT_TEMPLATE), "my-app--ci.yml");
+        assert_eq!(name("ci.toml", DEFAULT_TEMPLATE), "my-app--ci.yml");
+        assert_eq!(
+            name("ci.yaml", "{file}.{workspace}.{ext}"),
+            "ci.my-app.yaml"
+        );
+    }
+}
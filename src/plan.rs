@@ -0,0 +1,608 @@
+//! Computes the files hawk generates without touching the target directory.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::aggregate;
+use crate::filter::Filter;
+use crate::header::Provenance;
+use crate::include;
+use crate::models::config::Config;
+use crate::models::environment_files::list_files;
+use crate::models::manifest::Manifest;
+use crate::models::workspace::Workspace;
+use crate::transforms;
+use crate::utils;
+
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub content: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Missing,
+    Outdated,
+    /// Edited after hawk generated it
+    Modified,
+    UpToDate,
+}
+
+impl Output {
+    /// Compares the planned content with the file in the target directory.
+    pub fn status(&self, manifest: &Manifest) -> Status {
+        match fs::read(&self.target) {
+            Err(_) => Status::Missing,
+            Ok(content) if content == self.content => Status::UpToDate,
+            Ok(_) if manifest.is_modified(&self.target) => Status::Modified,
+            Ok(_) => Status::Outdated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub outputs: Vec<Output>,
+    /// Files in the workspace directory that are neither workflows nor included by one
+    pub skipped: usize,
+    /// Only some workflows of the workspace were planned, the outputs of the others are kept
+    pub is_partial: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    pub files: Vec<PathBuf>,
+    /// Files in the workspace directory that aren't workflows
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Workflows of the workspace: the files of its directory and subdirectories, then the shared
+/// templates it doesn't override with a file generating the same name, whatever its format.
+/// Files left out by the workspace filter are neither generated nor counted as skipped.
+pub fn sources(workspace: &Workspace, config: &Config) -> Sources {
+    let mut sources = Sources::default();
+    let filter = Filter::new(workspace);
+    let entries = WalkDir::new(&workspace.path)
+        .min_depth(1)
+        .sort_by_file_name();
+
+    for entry in entries {
+        match entry {
+            Ok(entry) if entry.file_type().is_dir() || filter.is_ignored(entry.path()) => {}
+            Ok(entry) if utils::is_workflow_file(entry.path()) => {
+                sources.files.push(entry.into_path())
+            }
+            Ok(entry) => sources.skipped.push(entry.into_path()),
+            Err(err) => println!("Failed to read: {}", err),
+        }
+    }
+
+    let templates = match &config.templates {
+        Some(templates) if templates.matches(workspace) => templates,
+        _ => return sources,
+    };
+
+    let targets: Vec<String> = sources
+        .files
+        .iter()
+        .map(|f| utils::target_filename(f, workspace, config))
+        .collect();
+
+    if let Ok(content) = fs::read_dir(&templates.path) {
+        for entry in content.flatten() {
+            let path = entry.path();
+            // `lint.toml` overrides `lint.yml`
+            let is_overridden = targets.contains(&utils::target_filename(&path, workspace, config));
+
+            let is_aggregate = config.aggregate.as_ref().is_some_and(|a| {
+                utils::normalize_path(Path::new(&a.source)) == utils::normalize_path(&path)
+            });
+
+            if utils::is_workflow_file(&path)
+                && !is_overridden
+                && !is_aggregate
+                && !filter.is_ignored(&path)
+            {
+                sources.files.push(path);
+            }
+        }
+    }
+
+    sources
+}
+
+/// Source of the workspace workflow at the given path, relative to the workspace directory
+/// (or the templates directory), if any.
+pub fn source(workspace: &Workspace, config: &Config, relative: &str) -> Option<PathBuf> {
+    sources(workspace, config)
+        .files
+        .into_iter()
+        .find(|f| utils::normalize_path(&utils::relative_source(f, workspace)) == relative)
+}
+
+/// Plans the output of every workflow in the workspace directory.
+pub fn workspace(workspace: &Workspace, config: &Config) -> io::Result<Plan> {
+    let sources = sources(workspace, config);
+    let mut plan = workflows(workspace, config, &sources.files)?;
+
+    // fragments kept next to the workflows are not skipped
+    let fragments: Vec<PathBuf> = match sources.skipped.is_empty() {
+        true => Vec::new(),
+        false => sources
+            .files
+            .iter()
+            .flat_map(|source| include::dependencies(source, workspace, config))
+            .map(|f| utils::canonical(&f))
+            .collect(),
+    };
+
+    plan.skipped = sources
+        .skipped
+        .iter()
+        .filter(|f| !fragments.contains(&utils::canonical(f)))
+        .count();
+    plan.is_partial = false;
+
+    Ok(plan)
+}
+
+/// Plans the output of the given workflows of the workspace only.
+pub fn workflows(workspace: &Workspace, config: &Config, sources: &[PathBuf]) -> io::Result<Plan> {
+    let names = transforms::Names::default();
+    let mut outputs = Vec::with_capacity(sources.len());
+
+    for source in sources {
+        outputs.push(Output {
+            target: utils::target_filename(source, workspace, config).into(),
+            content: utils::render_file(source, workspace, config, &names)?,
+            source: source.clone(),
+        });
+    }
+
+    Ok(Plan {
+        outputs,
+        skipped: 0,
+        is_partial: true,
+    })
+}
+
+/// Files recorded in the manifest for the workspace which are not part of the plan anymore.
+pub fn pruned(
+    manifest: &Manifest,
+    workspace: &Workspace,
+    target: &str,
+    plan: &Plan,
+) -> Vec<PathBuf> {
+    manifest
+        .workspace(&workspace.name)
+        .map(|(name, _)| Path::new(target).join(name))
+        .filter(|f| !plan.outputs.iter().any(|o| &o.target == f))
+        .collect()
+}
+
+/// Files in the target directory carrying a hawk header.
+pub fn generated(target: &str) -> Vec<(PathBuf, Provenance)> {
+    list_files(Path::new(target))
+        .into_iter()
+        .filter_map(|f| Provenance::read(&f).map(|p| (f, p)))
+        .collect()
+}
+
+/// Generated files of workspaces that are no longer configured, either recorded in the
+/// manifest or recognised by their header. The same goes for an aggregate workflow which is no
+/// longer configured or was renamed.
+pub fn removed(manifest: &Manifest, config: &Config) -> Vec<PathBuf> {
+    let target = &config.target;
+    let aggregate = config
+        .aggregate
+        .as_ref()
+        .map(|a| Path::new(target).join(a.filename()));
+    let is_configured = |name: &str, file: &PathBuf| {
+        config.workspaces.iter().any(|w| w.name == name)
+            || (name == aggregate::WORKSPACE && Some(file) == aggregate.as_ref())
+    };
+
+    let mut removed: Vec<PathBuf> = manifest
+        .files
+        .iter()
+        .map(|(name, e)| (Path::new(target).join(name), e))
+        .filter(|(f, e)| !is_configured(&e.workspace, f))
+        .map(|(f, _)| f)
+        .collect();
+
+    for (f, provenance) in generated(target) {
+        if !is_configured(&provenance.workspace, &f) && !removed.contains(&f) {
+            removed.push(f);
+        }
+    }
+
+    removed
+}
+
+/// Generated files of the workspace which are not part of the plan, either recorded in the
+/// manifest or recognised by their header.
+pub fn orphans(
+    manifest: &Manifest,
+    workspace: &Workspace,
+    target: &str,
+    plan: &Plan,
+) -> Vec<PathBuf> {
+    let mut orphans: Vec<PathBuf> = pruned(manifest, workspace, target, plan)
+        .into_iter()
+        .filter(|f| f.exists())
+        .collect();
+
+    for (f, provenance) in generated(target) {
+        if provenance.workspace == workspace.name
+            && !plan.outputs.iter().any(|o| o.target == f)
+            && !orphans.contains(&f)
+        {
+            orphans.push(f);
+        }
+    }
+
+    orphans
+}
+
+/// Outputs that cannot be written without overwriting something else
+#[derive(Debug, Clone)]
+pub enum Collision {
+    /// Two workspaces with the same name
+    Workspace { name: String, paths: [String; 2] },
+    /// A workspace name without any letter or digit, its files can't be named after it
+    EmptyName { name: String, path: String },
+    /// Two sources generating the same file, with their workspace names
+    Target {
+        target: PathBuf,
+        sources: [(String, PathBuf); 2],
+    },
+    /// A source generating a file that exists in the target directory and wasn't generated by hawk
+    Unmanaged { target: PathBuf, source: PathBuf },
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Collision::Workspace { name, paths } => write!(
+                f,
+                "workspace name {} is used by both {} and {}",
+                name, paths[0], paths[1]
+            ),
+            Collision::EmptyName { name, path } => write!(
+                f,
+                "workspace name {:?} ({}) has no letter or digit to name its files, rename it",
+                name, path
+            ),
+            Collision::Target { target, sources } => write!(
+                f,
+                "{} is generated by both {} ({}) and {} ({})",
+                target.display(),
+                sources[0].1.display(),
+                sources[0].0,
+                sources[1].1.display(),
+                sources[1].0
+            ),
+            Collision::Unmanaged { target, source } => write!(
+                f,
+                "{} (from {}) would overwrite a file not generated by hawk",
+                target.display(),
+                source.display()
+            ),
+        }
+    }
+}
+
+/// Checks the outputs of every workspace against each other and the target directory.
+pub fn collisions(config: &Config, manifest: &Manifest) -> Vec<Collision> {
+    let mut collisions = Vec::new();
+    let mut workspaces: Vec<&Workspace> = Vec::new();
+    let mut targets: Vec<(PathBuf, (String, PathBuf))> = Vec::new();
+
+    for workspace in &config.workspaces {
+        match workspaces.iter().find(|w| w.name == workspace.name) {
+            Some(other) => {
+                collisions.push(Collision::Workspace {
+                    name: workspace.name.clone(),
+                    paths: [other.path.clone(), workspace.path.clone()],
+                });
+
+                continue;
+            }
+            None => workspaces.push(workspace),
+        }
+
+        if config.naming().contains("{workspace}") && config.slug.apply(&workspace.name).is_empty()
+        {
+            collisions.push(Collision::EmptyName {
+                name: workspace.name.clone(),
+                path: workspace.path.clone(),
+            });
+
+            continue;
+        }
+
+        for source in sources(workspace, config).files {
+            let target = PathBuf::from(utils::target_filename(&source, workspace, config));
+
+            if let Some((_, other)) = targets.iter().find(|(t, _)| t == &target) {
+                collisions.push(Collision::Target {
+                    target: target.clone(),
+                    sources: [other.clone(), (workspace.name.clone(), source.clone())],
+                });
+            } else if is_unmanaged(&target, manifest) {
+                collisions.push(Collision::Unmanaged {
+                    target: target.clone(),
+                    source: source.clone(),
+                });
+            }
+
+            targets.push((target, (workspace.name.clone(), source)));
+        }
+    }
+
+    if let Some(aggregate) = &config.aggregate {
+        let target = Path::new(&config.target).join(aggregate.filename());
+
+        if let Some((_, other)) = targets.iter().find(|(t, _)| t == &target) {
+            collisions.push(Collision::Target {
+                target: target.clone(),
+                sources: [
+                    other.clone(),
+                    (aggregate::WORKSPACE.into(), aggregate.source.clone().into()),
+                ],
+            });
+        } else if is_unmanaged(&target, manifest) {
+            collisions.push(Collision::Unmanaged {
+                target,
+                source: aggregate.source.clone().into(),
+            });
+        }
+    }
+
+    collisions
+}
+
+/// Whether the target exists but is neither in the manifest nor recognised by its header.
+pub fn is_unmanaged(target: &Path, manifest: &Manifest) -> bool {
+    target.exists()
+        && !manifest.files.contains_key(&utils::file_name(target))
+        && Provenance::read(target).is_none()
+}
+
+/// A `workflow_run` reference matching workflows of several workspaces once generated
+#[derive(Debug, Clone)]
+pub struct Ambiguity {
+    pub source: PathBuf,
+    /// Final name of the referenced workflow
+    pub name: String,
+    pub workspaces: Vec<String>,
+}
+
+impl fmt::Display for Ambiguity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} is triggered by workflow_run of \"{}\", a name used by workflows of {}",
+            self.source.display(),
+            self.name,
+            self.workspaces.join(", ")
+        )
+    }
+}
+
+/// Original and final names of the workflows of every workspace, built once per run
+#[derive(Debug, Clone, Default)]
+pub struct NameIndex {
+    workspaces: Vec<(String, HashMap<String, String>)>,
+}
+
+impl NameIndex {
+    pub fn new(config: &Config) -> NameIndex {
+        NameIndex {
+            workspaces: config
+                .workspaces
+                .iter()
+                .map(|w| (w.name.clone(), transforms::names(w, config)))
+                .collect(),
+        }
+    }
+
+    /// Final name of a workflow of the workspace, referenced by its original name
+    fn resolve(&self, workspace: &str, name: String) -> String {
+        self.workspaces
+            .iter()
+            .find(|(w, _)| w == workspace)
+            .and_then(|(_, names)| names.get(&name).cloned())
+            .unwrap_or(name)
+    }
+
+    /// Workspaces generating a workflow with the given final name
+    fn workspaces(&self, name: &str) -> Vec<String> {
+        self.workspaces
+            .iter()
+            .filter(|(_, names)| names.values().any(|n| n == name))
+            .map(|(w, _)| w.clone())
+            .collect()
+    }
+}
+
+/// Checks the `workflow_run` references of the workspace against the names of every
+/// generated workflow.
+pub fn ambiguities(workspace: &Workspace, config: &Config, index: &NameIndex) -> Vec<Ambiguity> {
+    let mut ambiguities = Vec::new();
+
+    for source in sources(workspace, config).files {
+        let workflow = match utils::load_workflow(&source, workspace, config) {
+            Ok(workflow) => workflow,
+            Err(_) => continue,
+        };
+
+        let references = workflow
+            .on
+            .as_ref()
+            .and_then(|on| on.get("workflow_run"))
+            .and_then(|config| config.workflows.clone())
+            .unwrap_or_default();
+
+        for reference in references {
+            let name = index.resolve(&workspace.name, reference);
+            let workspaces = index.workspaces(&name);
+
+            if workspaces.len() > 1 {
+                ambiguities.push(Ambiguity {
+                    source: source.clone(),
+                    name,
+                    workspaces,
+                });
+            }
+        }
+    }
+
+    ambiguities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::templates::Aggregate;
+
+    const WORKFLOW: &str = "on: push\njobs:\n  build:\n    runs-on: ubuntu-latest\n";
+
+    /// Workspace directory with the given files, and a config targeting a sibling directory
+    fn setup(root: &Path, files: &[(&str, &str)]) -> (Workspace, Config) {
+        for (path, content) in files {
+            let path = root.join("workflows").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        fs::create_dir_all(root.join("target")).unwrap();
+
+        let workspace = Workspace {
+            name: "my-app".into(),
+            path: utils::normalize_path(&root.join("workflows")),
+            ..Default::default()
+        };
+        let mut config = Config::new(&utils::normalize_path(&root.join("target")));
+        config.workspaces = vec![workspace.clone()];
+
+        (workspace, config)
+    }
+
+    #[test]
+    fn flattens_subdirectories() {
+        let dir = tempfile::tempdir().unwrap();
+        let (workspace, config) = setup(dir.path(), &[("release/publish.yml", WORKFLOW)]);
+        let plan = super::workspace(&workspace, &config).unwrap();
+
+        assert_eq!(plan.outputs.len(), 1);
+        assert!(plan.outputs[0]
+            .target
+            .ends_with("my-app--release-publish.yml"));
+    }
+
+    #[test]
+    fn reports_flattened_collisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let (workspace, config) = setup(
+            dir.path(),
+            &[
+                ("release/publish.yml", WORKFLOW),
+                ("release-publish.yml", WORKFLOW),
+            ],
+        );
+        let collisions = collisions(&config, &Manifest::default());
+
+        assert_eq!(collisions.len(), 1, "{:?}", collisions);
+        assert!(matches!(
+            &collisions[0],
+            Collision::Target { target, .. } if target.ends_with("my-app--release-publish.yml")
+        ));
+        assert_eq!(sources(&workspace, &config).files.len(), 2);
+    }
+
+    #[test]
+    fn reports_hand_written_aggregate_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, mut config) = setup(dir.path(), &[]);
+        config.aggregate = Some(Aggregate {
+            source: "templates/ci-all.yml".into(),
+            filename: None,
+            jobs: vec![],
+        });
+        fs::write(Path::new(&config.target).join("ci-all.yml"), WORKFLOW).unwrap();
+
+        let collisions = collisions(&config, &Manifest::default());
+
+        assert_eq!(collisions.len(), 1, "{:?}", collisions);
+        assert!(matches!(
+            &collisions[0],
+            Collision::Unmanaged { target, .. } if target.ends_with("ci-all.yml")
+        ));
+    }
+
+    #[test]
+    fn included_fragments_are_not_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (workspace, config) = setup(dir.path(), &[("notes.txt", "")]);
+        let fragment = Path::new(&workspace.path).join("shared/steps.yml");
+        fs::create_dir_all(fragment.parent().unwrap()).unwrap();
+        fs::write(&fragment, "- run: make\n").unwrap();
+        fs::write(
+            Path::new(&workspace.path).join("ci.yml"),
+            format!(
+                "on: push\njobs:\n  build:\n    runs-on: ubuntu-latest\n    steps: !include {}\n",
+                fragment.display()
+            ),
+        )
+        .unwrap();
+
+        let plan = super::workspace(&workspace, &config).unwrap();
+
+        assert_eq!(plan.outputs.len(), 1);
+        assert_eq!(plan.skipped, 1);
+    }
+
+    fn index(workspaces: &[(&str, &[(&str, &str)])]) -> NameIndex {
+        NameIndex {
+            workspaces: workspaces
+                .iter()
+                .map(|(w, names)| {
+                    let names = names
+                        .iter()
+                        .map(|(from, to)| (from.to_string(), to.to_string()))
+                        .collect();
+
+                    (w.to_string(), names)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn resolves_references_within_the_workspace() {
+        let index = index(&[
+            ("web", &[("Build", "web: Build")]),
+            ("api", &[("Build", "api: Build")]),
+        ]);
+
+        assert_eq!(index.resolve("api", "Build".into()), "api: Build");
+        assert_eq!(index.resolve("api", "Deploy".into()), "Deploy");
+        assert_eq!(index.workspaces("api: Build"), vec!["api"]);
+    }
+
+    #[test]
+    fn finds_workspaces_sharing_a_name() {
+        let index = index(&[
+            ("web", &[("Build", "Build")]),
+            ("api", &[("Build", "Build")]),
+        ]);
+
+        assert_eq!(index.workspaces("Build"), vec!["web", "api"]);
+    }
+}
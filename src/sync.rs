@@ -0,0 +1,571 @@
+//! Brings the target directory in line with the plan of a workspace.
+//!
+//! `copy`, `clean`, `check` and the watchers all compare the planned outputs with the target
+//! directory through [`changes`], then `copy`, `clean` and the watchers write them with [`apply`].
+use colored::*;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::diff;
+use crate::diff::Change;
+use crate::header;
+use crate::models::config::Config;
+use crate::models::files::FileKind;
+use crate::models::manifest::{Entry, Manifest};
+use crate::models::workspace::Workspace;
+use crate::plan;
+use crate::plan::{Output, Plan, Status};
+use crate::transforms;
+use crate::utils;
+
+/// How generated files edited by hand are handled
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConflictOptions {
+    /// Overwrite or delete them
+    pub force: bool,
+    /// Merge their changes into their source
+    pub merge: bool,
+}
+
+/// How changes are reported and whether they are written
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreviewOptions {
+    /// Print the changes without writing or deleting files
+    pub dry_run: bool,
+    /// Print a unified diff of the changes
+    pub diff: bool,
+}
+
+impl PreviewOptions {
+    /// Whether planned operations should be printed
+    pub fn is_verbose(&self) -> bool {
+        self.dry_run || self.diff
+    }
+}
+
+/// What happens to a file of the target directory
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Creates or updates the file
+    Write(Output, Change),
+    UpToDate(Output),
+    /// The file was edited by hand and is left untouched
+    Conflict(Output),
+    /// The file was edited by hand, the changes are merged into its source
+    Merge(Output),
+    /// A generated file which is not part of the plan anymore, it may not exist
+    Remove(PathBuf),
+    /// A generated file which is not part of the plan anymore but was edited by hand, it is
+    /// left untouched
+    Edited(PathBuf),
+    /// A file recognised as generated by its header only, which is not part of the plan. It may
+    /// have been copied by hand, it is left untouched
+    Unrecorded(PathBuf),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    pub actions: Vec<Action>,
+    /// Files in the workspace directory that aren't workflows
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Summary {
+    /// Files written or up to date
+    pub copied: usize,
+    pub unchanged: usize,
+    pub conflicts: usize,
+}
+
+/// Compares the plan of the workspace with the target directory. Unless the plan is partial,
+/// generated files of the workspace that are not planned, recorded in the manifest or
+/// recognised by their header, are removed unless they were edited by hand.
+pub fn changes(
+    workspace: &Workspace,
+    config: &Config,
+    plan: Plan,
+    flags: &ConflictOptions,
+) -> io::Result<Changes> {
+    let target = &config.target;
+    let manifest = Manifest::read(target)?;
+    let mut removed = Vec::new();
+
+    if !plan.is_partial {
+        removed = plan::pruned(&manifest, workspace, target, &plan);
+
+        for orphan in plan::orphans(&manifest, workspace, target, &plan) {
+            if !removed.contains(&orphan) {
+                removed.push(orphan);
+            }
+        }
+    }
+
+    let mut actions: Vec<Action> = plan
+        .outputs
+        .into_iter()
+        .map(|output| match output.status(&manifest) {
+            Status::Missing => Action::Write(output, Change::Create),
+            Status::Outdated => Action::Write(output, Change::Update),
+            Status::Modified if flags.force => Action::Write(output, Change::Update),
+            Status::Modified if flags.merge => Action::Merge(output),
+            Status::Modified => Action::Conflict(output),
+            Status::UpToDate => Action::UpToDate(output),
+        })
+        .collect();
+
+    actions.extend(removed.into_iter().map(|f| removal(f, &manifest, flags)));
+
+    Ok(Changes {
+        actions,
+        skipped: plan.skipped,
+    })
+}
+
+/// Removes a generated file, unless it was edited by hand or isn't in the manifest, and `--force`
+/// isn't set.
+fn removal(file: PathBuf, manifest: &Manifest, flags: &ConflictOptions) -> Action {
+    if flags.force {
+        Action::Remove(file)
+    } else if !manifest.files.contains_key(&utils::file_name(&file)) {
+        Action::Unrecorded(file)
+    } else if manifest.is_modified(&file) {
+        Action::Edited(file)
+    } else {
+        Action::Remove(file)
+    }
+}
+
+/// Applies the changes to the target directory and the manifest.
+pub fn apply(
+    changes: &Changes,
+    workspace: &Workspace,
+    config: &Config,
+    flags: &PreviewOptions,
+) -> io::Result<Summary> {
+    let target = &config.target;
+    let manifest = Manifest::read(target)?;
+    let mut written = Vec::new();
+    let mut removed = Vec::new();
+    let mut summary = Summary::default();
+
+    for action in &changes.actions {
+        let (output, change) = match action {
+            Action::Write(output, change) => (output, *change),
+            Action::UpToDate(output) => {
+                summary.unchanged += 1;
+                written.push((output, output.content.clone()));
+                continue;
+            }
+            Action::Merge(output) => {
+                match merge_back(output, workspace, config, &manifest, flags)? {
+                    Some(content) => written.push((output, content)),
+                    None => summary.conflicts += 1,
+                }
+
+                continue;
+            }
+            Action::Conflict(output) => {
+                summary.conflicts += 1;
+                println!(
+                    "{} {} was edited by hand, use {} to overwrite it or {} to merge the changes into {}",
+                    "conflict".red().bold(),
+                    output.target.display().to_string().underline().blue(),
+                    "--force".bold(),
+                    "--merge".bold(),
+                    output.source.display()
+                );
+
+                continue;
+            }
+            Action::Remove(file) => {
+                removed.push(file.clone());
+                continue;
+            }
+            Action::Edited(_) | Action::Unrecorded(_) => {
+                summary.conflicts += 1;
+                report_kept(action);
+                continue;
+            }
+        };
+
+        if flags.is_verbose() {
+            let previous = fs::read(&output.target).ok();
+
+            diff::print_change(
+                change,
+                &output.target.display().to_string(),
+                previous.as_deref().unwrap_or_default(),
+                &output.content,
+                flags.diff,
+            );
+        }
+
+        if !flags.dry_run {
+            fs::write(&output.target, &output.content)?;
+        }
+
+        written.push((output, output.content.clone()));
+    }
+
+    remove(&removed, target, flags)?;
+
+    if !flags.dry_run {
+        Manifest::update(target, |m| {
+            for (output, content) in &written {
+                m.files.insert(
+                    utils::file_name(&output.target),
+                    Entry::new(&workspace.name, &output.source, content),
+                );
+            }
+        })
+        .map_err(io::Error::from)?;
+    }
+
+    summary.copied = written.len();
+
+    Ok(summary)
+}
+
+/// Deletes generated files and their manifest entries, except the ones edited by hand unless
+/// `--force` is set. Returns the number of files left untouched.
+pub fn prune(
+    files: Vec<PathBuf>,
+    target: &str,
+    flags: &PreviewOptions,
+    conflict_flags: &ConflictOptions,
+) -> io::Result<usize> {
+    let manifest = Manifest::read(target)?;
+    let mut removed = Vec::new();
+    let mut conflicts = 0;
+
+    for file in files {
+        match removal(file, &manifest, conflict_flags) {
+            Action::Remove(file) => removed.push(file),
+            action => {
+                conflicts += 1;
+                report_kept(&action);
+            }
+        }
+    }
+
+    remove(&removed, target, flags)?;
+
+    Ok(conflicts)
+}
+
+/// Reports a file which is no longer generated but left untouched.
+fn report_kept(action: &Action) {
+    let (file, reason) = match action {
+        Action::Edited(file) => (file, "was edited by hand"),
+        Action::Unrecorded(file) => (file, "isn't recorded in the manifest"),
+        _ => return,
+    };
+
+    println!(
+        "{} {} {} and is no longer generated, use {} to delete it",
+        "conflict".red().bold(),
+        file.display().to_string().underline().blue(),
+        reason,
+        "--force".bold(),
+    );
+}
+
+/// Deletes generated files and their manifest entries.
+pub fn remove(files: &[PathBuf], target: &str, flags: &PreviewOptions) -> io::Result<()> {
+    for filename in files {
+        let content = match fs::read(filename) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+
+        if flags.is_verbose() {
+            diff::print_change(
+                Change::Delete,
+                &filename.display().to_string(),
+                &content,
+                &[],
+                flags.diff,
+            );
+        }
+
+        if flags.dry_run {
+            continue;
+        }
+
+        fs::remove_file(filename)?;
+
+        if !flags.is_verbose() {
+            println!(
+                "Removing {}",
+                filename.display().to_string().underline().blue()
+            );
+        }
+    }
+
+    if !flags.dry_run {
+        Manifest::update(target, |m| {
+            for filename in files {
+                m.files.remove(&utils::file_name(filename));
+            }
+        })
+        .map_err(io::Error::from)?;
+    }
+
+    Ok(())
+}
+
+/// Copies the changes made to a generated file into its source, then regenerates it. Only a
+/// file generated from the current source as is can be copied back.
+/// Returns the new generated content, `None` if the changes could not be merged.
+fn merge_back(
+    output: &Output,
+    workspace: &Workspace,
+    config: &Config,
+    manifest: &Manifest,
+    flags: &PreviewOptions,
+) -> io::Result<Option<Vec<u8>>> {
+    let recorded = manifest.files.get(&utils::file_name(&output.target));
+
+    // the edits are the only changes only if nothing else changed since the file was generated
+    if recorded.map(|e| &e.hash) != Some(&utils::hash(&output.content)) {
+        println!(
+            "{} cannot merge {}: its source or the config changed since it was generated",
+            "conflict".red().bold(),
+            output.target.display().to_string().underline().blue(),
+        );
+
+        return Ok(None);
+    }
+
+    if !matches!(FileKind::from_path(&output.source), Ok(FileKind::YAML)) {
+        println!(
+            "{} cannot merge {}: its source {} is not a YAML file",
+            "conflict".red().bold(),
+            output.target.display().to_string().underline().blue(),
+            output.source.display()
+        );
+
+        return Ok(None);
+    }
+
+    let base = String::from_utf8_lossy(&output.content).to_string();
+    let edited = fs::read_to_string(&output.target)?;
+    let source = fs::read_to_string(&output.source)?;
+    let source_path = output.source.display().to_string();
+
+    // the edited file can replace the source only if it was generated as is, the placeholders,
+    // includes or transforms of the source would be lost
+    if header::strip(&base) != source {
+        println!(
+            "{} cannot merge {}: hawk rewrote {} while generating it, edit the source instead",
+            "conflict".red().bold(),
+            output.target.display().to_string().underline().blue(),
+            source_path
+        );
+
+        return Ok(None);
+    }
+
+    // the source didn't change since, the edited file without its header is the merged source
+    let merged = header::strip(&edited);
+
+    println!(
+        "{} {} into {}",
+        "merge".cyan(),
+        output.target.display().to_string().underline().blue(),
+        source_path.underline().blue()
+    );
+
+    if flags.diff {
+        diff::print_unified(&source_path, &source, merged);
+    }
+
+    if flags.dry_run {
+        return Ok(Some(output.content.clone()));
+    }
+
+    fs::write(&output.source, merged)?;
+
+    let names = transforms::Names::default();
+    let content = utils::render_file(&output.source, workspace, config, &names)?;
+    fs::write(&output.target, &content)?;
+
+    Ok(Some(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Provenance;
+    use std::path::Path;
+
+    struct Setup {
+        workspace: Workspace,
+        config: Config,
+        /// Target directory, removed when the setup is dropped
+        _target: tempfile::TempDir,
+    }
+
+    impl Setup {
+        fn new() -> Setup {
+            let target = tempfile::tempdir().unwrap();
+
+            Setup {
+                workspace: Workspace {
+                    name: "my-app".into(),
+                    path: "packages/my-app/workflows".into(),
+                    ..Default::default()
+                },
+                config: Config::new(&utils::normalize_path(target.path())),
+                _target: target,
+            }
+        }
+
+        fn output(&self, name: &str, content: &str) -> Output {
+            Output {
+                target: Path::new(&self.config.target).join(name),
+                source: Path::new(&self.workspace.path).join(name),
+                content: content.into(),
+            }
+        }
+
+        /// A file generated with `generated`, then changed to `current`
+        fn generated(&self, name: &str, generated: &str, current: &str) -> PathBuf {
+            let output = self.output(name, generated);
+            fs::write(&output.target, current).unwrap();
+            Manifest::update(&self.config.target, |m| {
+                m.files.insert(
+                    name.into(),
+                    Entry::new(&self.workspace.name, &output.source, generated.as_bytes()),
+                );
+            })
+            .unwrap();
+
+            output.target
+        }
+
+        fn changes(&self, outputs: Vec<Output>, flags: ConflictOptions) -> Vec<Action> {
+            let plan = Plan {
+                outputs,
+                ..Default::default()
+            };
+
+            changes(&self.workspace, &self.config, plan, &flags)
+                .unwrap()
+                .actions
+        }
+    }
+
+    #[test]
+    fn writes_missing_and_outdated_files() {
+        let setup = Setup::new();
+        setup.generated("b.yml", "old", "old");
+        setup.generated("c.yml", "same", "same");
+
+        let actions = setup.changes(
+            vec![
+                setup.output("a.yml", "new"),
+                setup.output("b.yml", "new"),
+                setup.output("c.yml", "same"),
+            ],
+            ConflictOptions::default(),
+        );
+
+        assert!(matches!(&actions[0], Action::Write(_, Change::Create)));
+        assert!(matches!(&actions[1], Action::Write(_, Change::Update)));
+        assert!(matches!(&actions[2], Action::UpToDate(_)));
+        assert_eq!(actions.len(), 3);
+    }
+
+    #[test]
+    fn leaves_modified_files_alone() {
+        let setup = Setup::new();
+        setup.generated("a.yml", "old", "edited");
+        let outputs = vec![setup.output("a.yml", "new")];
+
+        let actions = setup.changes(outputs.clone(), ConflictOptions::default());
+        assert!(matches!(&actions[..], [Action::Conflict(_)]));
+
+        let force = ConflictOptions {
+            force: true,
+            ..Default::default()
+        };
+        let actions = setup.changes(outputs.clone(), force);
+        assert!(matches!(&actions[..], [Action::Write(_, Change::Update)]));
+
+        let merge = ConflictOptions {
+            merge: true,
+            ..Default::default()
+        };
+        let actions = setup.changes(outputs, merge);
+        assert!(matches!(&actions[..], [Action::Merge(_)]));
+    }
+
+    #[test]
+    fn prunes_files_no_longer_planned() {
+        let setup = Setup::new();
+        let removed = setup.generated("a.yml", "old", "old");
+        let edited = setup.generated("b.yml", "old", "edited");
+
+        let actions = setup.changes(Vec::new(), ConflictOptions::default());
+        assert!(
+            matches!(&actions[..], [Action::Remove(f), Action::Edited(e)] if *f == removed && *e == edited)
+        );
+
+        let force = ConflictOptions {
+            force: true,
+            ..Default::default()
+        };
+        let actions = setup.changes(Vec::new(), force);
+        assert!(matches!(
+            &actions[..],
+            [Action::Remove(_), Action::Remove(_)]
+        ));
+    }
+
+    #[test]
+    fn keeps_unrecorded_files_unless_forced() {
+        let setup = Setup::new();
+        let provenance = Provenance {
+            workspace: setup.workspace.name.clone(),
+            source: "packages/my-app/workflows/a.yml".into(),
+            hash: "0".into(),
+        };
+        let copied = Path::new(&setup.config.target).join("a.yml");
+        fs::write(&copied, provenance.render(setup.config.header())).unwrap();
+
+        let actions = setup.changes(Vec::new(), ConflictOptions::default());
+        assert!(matches!(&actions[..], [Action::Unrecorded(f)] if *f == copied));
+
+        let force = ConflictOptions {
+            force: true,
+            ..Default::default()
+        };
+        let actions = setup.changes(Vec::new(), force);
+        assert!(matches!(&actions[..], [Action::Remove(f)] if *f == copied));
+    }
+
+    #[test]
+    fn partial_plans_prune_nothing() {
+        let setup = Setup::new();
+        setup.generated("a.yml", "old", "old");
+
+        let plan = Plan {
+            outputs: vec![setup.output("b.yml", "new")],
+            is_partial: true,
+            ..Default::default()
+        };
+        let actions = changes(
+            &setup.workspace,
+            &setup.config,
+            plan,
+            &ConflictOptions::default(),
+        )
+        .unwrap()
+        .actions;
+
+        assert!(matches!(&actions[..], [Action::Write(_, Change::Create)]));
+    }
+}
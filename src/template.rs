@@ -0,0 +1,302 @@
+//! Variables rendered in source workflows before they are parsed.
+//!
+//! ```yaml
+//! name: Deploy {{ workspace.name }} v{{ workspace.package.version }}
+//! env:
+//!   REGION: {{ vars.region }}
+//! ```
+//!
+//! Only `workspace.*` and `vars.*` placeholders are rendered, GitHub `${{ }}` expressions and
+//! other `{{ }}` (docker formats, ...) are left as they are.
+use serde_yaml::Value;
+use std::fmt;
+use std::path::Path;
+
+use crate::models::config::Config;
+use crate::models::workspace::Workspace;
+use crate::utils;
+
+pub type Result<T> = std::result::Result<T, TemplateError>;
+
+#[derive(Debug)]
+pub enum TemplateError {
+    UnknownVariable {
+        source: String,
+        line: usize,
+        name: String,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::UnknownVariable { source, line, name } => {
+                write!(f, "{}:{}: unknown variable `{}`", source, line, name)
+            }
+        }
+    }
+}
+
+impl From<TemplateError> for std::io::Error {
+    fn from(e: TemplateError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    }
+}
+
+const NAMESPACES: [&str; 2] = ["workspace", "vars"];
+
+/// Replaces the placeholders of the source workflow. A value filling a whole scalar is quoted
+/// when needed, `@acme/app` or `yes` stay strings.
+pub fn render(
+    content: &str,
+    source: &Path,
+    workspace: &Workspace,
+    config: &Config,
+) -> Result<String> {
+    replace(content, |name, is_scalar, line| {
+        let value =
+            lookup(name, workspace, config).ok_or_else(|| TemplateError::UnknownVariable {
+                source: utils::normalize_path(source),
+                line,
+                name: name.into(),
+            })?;
+
+        Ok(match value {
+            Value::String(s) if is_scalar => quote(s),
+            value => scalar(&value).unwrap_or_default(),
+        })
+    })
+}
+
+/// Replaces the placeholders with a plain word, to tell whether an unrendered source parses.
+pub fn mask(content: &str) -> String {
+    replace(content, |_, _, _| Ok("placeholder".into())).unwrap_or_else(|_| content.into())
+}
+
+/// Replaces the placeholders with `f(name, fills a whole scalar, line)`.
+fn replace<F>(content: &str, mut f: F) -> Result<String>
+where
+    F: FnMut(&str, bool, usize) -> Result<String>,
+{
+    let mut rendered = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end + 2,
+            None => break,
+        };
+
+        let name = rest[start + 2..end - 2].trim();
+        let is_expression = rest[..start].ends_with('$');
+
+        rendered.push_str(&rest[..start]);
+
+        if is_expression || !is_placeholder(name) {
+            rendered.push_str(&rest[start..end]);
+        } else {
+            let offset = content.len() - rest.len() + start;
+            let line_start = content[..offset].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = rest[end..].find('\n').map_or(rest.len(), |i| end + i);
+            let is_scalar = fills_scalar(&content[line_start..offset], &rest[end..line_end]);
+            let line = content[..offset].matches('\n').count() + 1;
+
+            rendered.push_str(&f(name, is_scalar, line)?);
+        }
+
+        rest = &rest[end..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Whether a placeholder between `before` and `after` on its line is a whole block scalar:
+/// `key: {{ vars.x }}` or `- {{ vars.x }}`, optionally followed by a comment.
+fn fills_scalar(before: &str, after: &str) -> bool {
+    if !before.is_empty() && !before.ends_with(char::is_whitespace) {
+        return false;
+    }
+
+    let after = after.trim_end();
+    let is_comment = after.starts_with(' ') && after.trim_start().starts_with('#');
+
+    if !after.is_empty() && !is_comment {
+        return false;
+    }
+
+    let mut prefix = before.trim();
+
+    while let Some(item) = prefix.strip_prefix('-') {
+        if !item.is_empty() && !item.starts_with(' ') {
+            break;
+        }
+
+        prefix = item.trim_start();
+    }
+
+    match prefix.strip_suffix(':') {
+        Some(key) => !key.contains(": ") && !key.contains(" #"),
+        None => prefix.is_empty(),
+    }
+}
+
+/// The string as a YAML scalar, quoted if it would read as something else
+fn quote(s: String) -> String {
+    match serde_yaml::to_string(&s) {
+        Ok(yaml) if yaml.trim_end().lines().count() == 1 => yaml.trim_end().into(),
+        _ => serde_json::to_string(&s).unwrap_or(s),
+    }
+}
+
+/// `workspace.name`, `vars.node-version`, ...
+fn is_placeholder(name: &str) -> bool {
+    let namespace = name.split('.').next().unwrap_or_default();
+
+    NAMESPACES.contains(&namespace)
+        && name.contains('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+}
+
+fn lookup(name: &str, workspace: &Workspace, config: &Config) -> Option<Value> {
+    match name.split_once('.')? {
+        ("workspace", "name") => Some(workspace.name.clone().into()),
+        ("workspace", "path") => Some(workspace.path.clone().into()),
+        ("workspace", "root") => Some(workspace.root().into()),
+        ("workspace", key) => {
+            let mut value = workspace.package()?;
+
+            for key in key.strip_prefix("package.")?.split('.') {
+                value = value.get(key)?.clone();
+            }
+
+            match value {
+                serde_json::Value::Object(_)
+                | serde_json::Value::Array(_)
+                | serde_json::Value::Null => None,
+                value => serde_yaml::to_value(value).ok(),
+            }
+        }
+        ("vars", key) => workspace
+            .vars
+            .get(key)
+            .or_else(|| config.vars.get(key))
+            .filter(|value| scalar(value).is_some())
+            .cloned(),
+        _ => None,
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn workspace() -> Workspace {
+        Workspace {
+            name: "@acme/app".into(),
+            path: "packages/app/workflows".into(),
+            ..Default::default()
+        }
+    }
+
+    fn render(content: &str, workspace: &Workspace, config: &Config) -> Result<String> {
+        super::render(content, Path::new("ci.yml"), workspace, config)
+    }
+
+    #[test]
+    fn quotes_values_filling_a_scalar() {
+        let mut config = Config::new(".github/workflows");
+        config.vars.insert("enabled".into(), "yes".into());
+        config.vars.insert("node".into(), 20.into());
+
+        let rendered = render(
+            "name: Build {{ workspace.name }}\nenv:\n  APP: {{ workspace.name }} # npm\n  ENABLED: {{ vars.enabled }}\n  NODE: {{ vars.node }}\nargs:\n  - {{ workspace.name }}\n",
+            &workspace(),
+            &config,
+        )
+        .unwrap();
+        let value: Value = serde_yaml::from_str(&rendered).unwrap();
+
+        assert_eq!(value["name"], "Build @acme/app");
+        assert_eq!(value["env"]["APP"], "@acme/app");
+        assert_eq!(value["env"]["ENABLED"], "yes");
+        assert_eq!(value["env"]["NODE"], 20);
+        assert_eq!(value["args"][0], "@acme/app");
+    }
+
+    #[test]
+    fn leaves_other_braces_alone() {
+        let content = "run: docker inspect -f '{{ .State.Running }}' ${{ matrix.workspace.name }} {{ secrets }}\n";
+        let rendered = render(content, &workspace(), &Config::new(".github/workflows")).unwrap();
+
+        assert_eq!(rendered, content);
+    }
+
+    #[test]
+    fn reports_the_line_of_unknown_variables() {
+        let err = render(
+            "name: CI\non: push\nenv:\n  REGION: {{ vars.region }}\n",
+            &workspace(),
+            &Config::new(".github/workflows"),
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            err,
+            TemplateError::UnknownVariable { line: 4, ref name, .. } if name == "vars.region"
+        ));
+        assert_eq!(err.to_string(), "ci.yml:4: unknown variable `vars.region`");
+    }
+
+    #[test]
+    fn reads_package_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("package.json"),
+            r#"{ "version": "1.2.0", "engines": { "node": ">=20" }, "private": true }"#,
+        )
+        .unwrap();
+
+        let workspace = Workspace {
+            package_json: Some(utils::normalize_path(dir.path())),
+            ..workspace()
+        };
+        let config = Config::new(".github/workflows");
+        let rendered = render(
+            "v{{ workspace.package.version }} {{ workspace.package.engines.node }} {{ workspace.package.private }}",
+            &workspace,
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(rendered, "v1.2.0 >=20 true");
+        assert!(render("{{ workspace.package.engines }}", &workspace, &config).is_err());
+        assert!(render("{{ workspace.package.missing }}", &workspace, &config).is_err());
+    }
+
+    #[test]
+    fn workspace_vars_win_over_config_vars() {
+        let mut config = Config::new(".github/workflows");
+        config.vars.insert("region".into(), "us-east-1".into());
+        config.vars.insert("node".into(), 20.into());
+
+        let mut workspace = workspace();
+        workspace.vars.insert("region".into(), "eu-west-1".into());
+
+        let rendered = render("{{ vars.region }} {{ vars.node }}", &workspace, &config).unwrap();
+
+        assert_eq!(rendered, "eu-west-1 20");
+    }
+}
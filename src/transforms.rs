@@ -0,0 +1,566 @@
+//! Rewrites applied to workflows while they are copied into the target directory.
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::models::config::Config;
+use crate::models::transforms::ConcurrencyOptions;
+use crate::models::workflow::{
+    Concurrency, ConcurrencyConfig, Defaults, EventConfig, Job, On, Trigger, Workflow,
+};
+use crate::models::workspace::Workspace;
+use crate::naming;
+use crate::plan;
+use crate::utils;
+use serde_yaml::Value;
+
+pub type Result<T> = std::result::Result<T, TransformError>;
+
+#[derive(Debug)]
+pub enum TransformError {
+    /// A job calls a local reusable workflow that doesn't exist
+    ReusableWorkflowNotFound {
+        source: String,
+        job: String,
+        uses: String,
+    },
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransformError::ReusableWorkflowNotFound { source, job, uses } => write!(
+                f,
+                "{}: job `{}` uses {}, which is neither a workflow of the workspace nor an existing workflow",
+                source, job, uses
+            ),
+        }
+    }
+}
+
+impl From<TransformError> for std::io::Error {
+    fn from(e: TransformError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+    }
+}
+
+/// What a workflow is being generated from and for
+pub struct Context<'a> {
+    pub source: &'a Path,
+    pub workspace: &'a Workspace,
+    pub config: &'a Config,
+    /// Names of the workspace workflows, shared by the workflows of a plan
+    pub names: &'a Names,
+}
+
+/// Original and final names of the workflows of a workspace, computed on first use: every
+/// workflow of the workspace has to be loaded.
+#[derive(Debug, Default)]
+pub struct Names(OnceCell<HashMap<String, String>>);
+
+impl Names {
+    pub fn get(&self, workspace: &Workspace, config: &Config) -> &HashMap<String, String> {
+        self.0.get_or_init(|| names(workspace, config))
+    }
+}
+
+/// Events supporting `paths` / `paths-ignore` filters
+const PATH_EVENTS: [&str; 3] = ["push", "pull_request", "pull_request_target"];
+
+/// Applies the transforms enabled for the workspace. Returns whether the workflow changed.
+pub fn apply(workflow: &mut Workflow, ctx: &Context) -> Result<bool> {
+    let mut changed = reusable_workflows(workflow, ctx)?;
+    changed |= workflow_run(workflow, ctx);
+
+    if ctx.workspace.transforms.working_directory {
+        changed |= working_directory(workflow, &ctx.workspace.root());
+    }
+
+    if let Some(shared) = &ctx.workspace.transforms.sparse_checkout {
+        changed |= sparse_checkout(workflow, &ctx.workspace.root(), shared);
+    }
+
+    if let Some(options) = &ctx.config.concurrency {
+        let relative = utils::relative_source(ctx.source, ctx.workspace);
+        changed |= concurrency(workflow, options, &ctx.workspace.name, &relative);
+    }
+
+    if let Some(template) = &ctx.workspace.transforms.name {
+        changed |= rename(workflow, template, &ctx.workspace.name, ctx.source);
+    }
+
+    if ctx.workspace.transforms.paths_filter {
+        changed |= paths_filter(workflow, &ctx.workspace.root());
+    }
+
+    Ok(changed)
+}
+
+/// Points `jobs.*.uses` references to workflows of the same workspace to their generated file.
+///
+/// Both `./.github/workflows/build.yml` (as if the source already was in the target directory)
+/// and `./packages/my-app/workflows/build.yml` are rewritten, as well as references to
+/// workflows in subdirectories (`./packages/my-app/workflows/release/publish.yml`) and to shared
+/// templates.
+pub fn reusable_workflows(workflow: &mut Workflow, ctx: &Context) -> Result<bool> {
+    let target = utils::normalize_path(Path::new(&ctx.config.target));
+    let workspace_dir = utils::normalize_path(Path::new(&ctx.workspace.path));
+    let templates_dir = ctx
+        .config
+        .templates
+        .as_ref()
+        .map(|t| utils::normalize_path(Path::new(&t.path)));
+    let mut changed = false;
+
+    for (name, job) in workflow.jobs.iter_mut() {
+        let job = match job {
+            Job::Reusable(job) if job.uses.starts_with("./") => job,
+            _ => continue,
+        };
+
+        let uses = Path::new(&job.uses);
+        let dir = utils::normalize_path(uses.parent().unwrap_or_else(|| Path::new("")));
+        let filename = utils::file_name(uses);
+        let subdir = dir
+            .strip_prefix(&workspace_dir)
+            .and_then(|d| d.strip_prefix('/'));
+
+        let relative = match subdir {
+            Some(subdir) => format!("{}/{}", subdir, filename),
+            None if dir == target || dir == workspace_dir => filename.clone(),
+            None if Some(&dir) == templates_dir.as_ref() => filename.clone(),
+            None => continue,
+        };
+
+        if let Some(sibling) = plan::source(ctx.workspace, ctx.config, &relative) {
+            let generated = utils::target_filename(&sibling, ctx.workspace, ctx.config);
+            job.uses = format!("./{}", utils::normalize_path(Path::new(&generated)));
+            changed = true;
+            continue;
+        }
+
+        // a workflow living in the target directory and not managed by hawk
+        if dir == target && Path::new(&ctx.config.target).join(&filename).exists() {
+            continue;
+        }
+
+        return Err(TransformError::ReusableWorkflowNotFound {
+            source: utils::normalize_path(ctx.source),
+            job: name.clone(),
+            uses: job.uses.clone(),
+        });
+    }
+
+    Ok(changed)
+}
+
+/// Renders the name template. Workflows without a name fall back to their filename, as in
+/// the GitHub UI.
+pub fn display_name(template: &str, workspace: &str, workflow: &Workflow, source: &Path) -> String {
+    let name = match workflow.name.as_str() {
+        "" => utils::file_name(source),
+        name => name.into(),
+    };
+
+    template
+        .replace("{workspace}", workspace)
+        .replace("{name}", &name)
+}
+
+/// Rewrites the workflow `name` with the template. Returns whether the workflow changed.
+pub fn rename(workflow: &mut Workflow, template: &str, workspace: &str, source: &Path) -> bool {
+    let name = display_name(template, workspace, workflow, source);
+
+    if name == workflow.name {
+        return false;
+    }
+
+    workflow.name = name;
+    true
+}
+
+/// Original and final names of the workflows of the workspace.
+pub fn names(workspace: &Workspace, config: &Config) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+
+    for source in plan::sources(workspace, config).files {
+        let workflow = match utils::load_workflow(&source, workspace, config) {
+            Ok(workflow) if !workflow.name.is_empty() => workflow,
+            _ => continue,
+        };
+
+        let name = match &workspace.transforms.name {
+            Some(template) => display_name(template, &workspace.name, &workflow, &source),
+            None => workflow.name.clone(),
+        };
+
+        names.insert(workflow.name, name);
+    }
+
+    names
+}
+
+/// Points `on.workflow_run.workflows` references to workflows of the same workspace to their
+/// final name. Returns whether the workflow changed.
+pub fn workflow_run(workflow: &mut Workflow, ctx: &Context) -> bool {
+    let workflows = match workflow
+        .on
+        .as_mut()
+        .and_then(|on| on.get_mut("workflow_run"))
+        .and_then(|config| config.workflows.as_mut())
+    {
+        Some(workflows) => workflows,
+        None => return false,
+    };
+
+    let names = ctx.names.get(ctx.workspace, ctx.config);
+    let mut changed = false;
+
+    for referenced in workflows.iter_mut() {
+        match names.get(referenced) {
+            Some(name) if name != referenced => {
+                *referenced = name.clone();
+                changed = true;
+            }
+            _ => {}
+        }
+    }
+
+    changed
+}
+
+/// Runs the steps from `root` by default. A `working-directory` already set at workflow level
+/// is kept, job level defaults take precedence anyway. Returns whether the workflow changed.
+pub fn working_directory(workflow: &mut Workflow, root: &str) -> bool {
+    if root.is_empty() {
+        return false;
+    }
+
+    let run = workflow
+        .defaults
+        .get_or_insert_with(Defaults::default)
+        .run
+        .get_or_insert_with(Default::default);
+
+    if run.working_directory.is_some() {
+        return false;
+    }
+
+    run.working_directory = Some(root.into());
+    true
+}
+
+/// Adds `sparse-checkout` of `root` and the shared paths to `actions/checkout` steps which
+/// don't set it. Returns whether the workflow changed.
+pub fn sparse_checkout(workflow: &mut Workflow, root: &str, shared: &[String]) -> bool {
+    if root.is_empty() {
+        return false;
+    }
+
+    let mut paths = vec![root.to_string()];
+    paths.extend(shared.iter().filter(|p| p.as_str() != root).cloned());
+
+    let mut changed = false;
+
+    for job in workflow.jobs.values_mut() {
+        let job = match job {
+            Job::Normal(job) => job,
+            Job::Reusable(_) => continue,
+        };
+
+        for step in job.steps.iter_mut() {
+            let is_checkout = step
+                .uses
+                .as_deref()
+                .is_some_and(|u| u.split('@').next() == Some("actions/checkout"));
+
+            if !is_checkout {
+                continue;
+            }
+
+            let with = step.with.get_or_insert_with(Default::default);
+
+            if with.contains_key("sparse-checkout") {
+                continue;
+            }
+
+            with.insert("sparse-checkout".into(), Value::from(paths.join("\n")));
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// Adds a `concurrency` block unique to the workspace workflow, unless the workflow already
+/// has one. Returns whether the workflow changed.
+pub fn concurrency(
+    workflow: &mut Workflow,
+    options: &ConcurrencyOptions,
+    workspace: &str,
+    source: &Path,
+) -> bool {
+    if workflow.concurrency.is_some() {
+        return false;
+    }
+
+    let file = naming::file(source);
+
+    workflow.concurrency = Some(Concurrency::Config(ConcurrencyConfig {
+        group: options
+            .group()
+            .replace("{workspace}", workspace)
+            .replace("{file}", &file),
+        cancel_in_progress: Some(Value::Bool(options.cancel_in_progress)),
+        ..Default::default()
+    }));
+
+    true
+}
+
+/// Scopes `push` and `pull_request` triggers to `root`. Events which already filter `paths`
+/// are left alone: GitHub runs the workflow when any pattern matches, so adding the workspace
+/// would widen them. Returns whether the workflow changed.
+pub fn paths_filter(workflow: &mut Workflow, root: &str) -> bool {
+    if root.is_empty() {
+        return false;
+    }
+
+    let on = match workflow.on.take() {
+        Some(on) if PATH_EVENTS.iter().any(|e| on.contains(e)) => on,
+        on => {
+            workflow.on = on;
+            return false;
+        }
+    };
+
+    let original = on.clone();
+    let pattern = format!("{}/**", root);
+    let mut events = on.into_map();
+    let mut changed = false;
+
+    for (event, trigger) in events.iter_mut() {
+        if !PATH_EVENTS.contains(&event.as_str()) {
+            continue;
+        }
+
+        let mut config = match trigger.take() {
+            None => Box::default(),
+            Some(Trigger::Config(config)) => config,
+            Some(other) => {
+                *trigger = Some(other);
+                continue;
+            }
+        };
+
+        changed |= scope_paths(&mut config, &pattern);
+        *trigger = Some(Trigger::Config(config));
+    }
+
+    workflow.on = Some(match changed {
+        true => On::Map(events),
+        false => original,
+    });
+
+    changed
+}
+
+/// Sets `paths` to the pattern, unless the event already has a `paths` list.
+fn scope_paths(config: &mut EventConfig, pattern: &str) -> bool {
+    if config.paths.is_some() {
+        return false;
+    }
+
+    let mut paths = vec![pattern.to_string()];
+
+    // `paths` and `paths-ignore` cannot be used together on the same event,
+    // negated patterns in `paths` have the same meaning.
+    if let Some(ignored) = config.paths_ignore.take() {
+        paths.extend(ignored.iter().map(|p| format!("!{}", p)));
+    }
+
+    config.paths = Some(paths);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::templates::Templates;
+    use std::fs;
+
+    const WORKFLOW: &str = "on: workflow_call\njobs:\n  build:\n    runs-on: ubuntu-latest\n";
+
+    /// Workspace with `build.yml` and `release/publish.yml`, a `lint.yml` template and a
+    /// hand-written `hand.yml` in the target directory, with the path of `dir` relative to the
+    /// crate: `uses` paths are relative to the repository.
+    fn reusable_setup(dir: &Path) -> (Workspace, Config, String) {
+        let cwd = std::env::current_dir().unwrap();
+        let root = utils::normalize_path(dir.strip_prefix(cwd).unwrap());
+
+        for path in [
+            "my-app/build.yml",
+            "my-app/release/publish.yml",
+            "templates/lint.yml",
+        ] {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, WORKFLOW).unwrap();
+        }
+
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("target/hand.yml"), WORKFLOW).unwrap();
+
+        let workspace = Workspace {
+            name: "my-app".into(),
+            path: format!("./{}/my-app", root),
+            ..Default::default()
+        };
+        let mut config = Config::new(&format!("{}/target", root));
+        config.templates = Some(Templates {
+            path: format!("{}/templates", root),
+            workspaces: vec![],
+            tags: vec![],
+        });
+        config.workspaces = vec![workspace.clone()];
+
+        (workspace, config, root)
+    }
+
+    /// `uses` of a job calling the given workflow, once rewritten
+    fn rewrite(uses: &str, workspace: &Workspace, config: &Config) -> Result<String> {
+        let mut workflow: Workflow =
+            serde_yaml::from_str(&format!("on: push\njobs:\n  call:\n    uses: {}\n", uses))
+                .unwrap();
+        let names = Names::default();
+        let ctx = Context {
+            source: Path::new("ci.yml"),
+            workspace,
+            config,
+            names: &names,
+        };
+
+        reusable_workflows(&mut workflow, &ctx)?;
+
+        match &workflow.jobs["call"] {
+            Job::Reusable(job) => Ok(job.uses.clone()),
+            Job::Normal(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn reusable_workflows_point_at_generated_files() {
+        let dir = tempfile::tempdir_in("target").unwrap();
+        let (workspace, config, root) = reusable_setup(dir.path());
+        let generated = |name: &str| format!("./{}/target/my-app--{}", root, name);
+
+        let target_form = format!("./{}/target/build.yml", root);
+        let workspace_form = format!("./{}/my-app/build.yml", root);
+        let subdir_form = format!("./{}/my-app/release/publish.yml", root);
+        let template_form = format!("./{}/templates/lint.yml", root);
+
+        assert_eq!(
+            rewrite(&target_form, &workspace, &config).unwrap(),
+            generated("build.yml")
+        );
+        assert_eq!(
+            rewrite(&workspace_form, &workspace, &config).unwrap(),
+            generated("build.yml")
+        );
+        assert_eq!(
+            rewrite(&subdir_form, &workspace, &config).unwrap(),
+            generated("release-publish.yml")
+        );
+        assert_eq!(
+            rewrite(&template_form, &workspace, &config).unwrap(),
+            generated("lint.yml")
+        );
+
+        let remote = "acme/ci/.github/workflows/build.yml@v1";
+        assert_eq!(rewrite(remote, &workspace, &config).unwrap(), remote);
+    }
+
+    #[test]
+    fn reusable_workflows_must_exist() {
+        let dir = tempfile::tempdir_in("target").unwrap();
+        let (workspace, config, root) = reusable_setup(dir.path());
+
+        // hand-written workflow of the target directory
+        let hand = format!("./{}/target/hand.yml", root);
+        assert_eq!(rewrite(&hand, &workspace, &config).unwrap(), hand);
+
+        for missing in [
+            format!("./{}/target/missing.yml", root),
+            format!("./{}/my-app/missing.yml", root),
+        ] {
+            let err = rewrite(&missing, &workspace, &config).unwrap_err();
+
+            assert!(matches!(
+                err,
+                TransformError::ReusableWorkflowNotFound { ref job, ref uses, .. }
+                    if job == "call" && *uses == missing
+            ));
+        }
+    }
+
+    fn workflow(on: &str) -> Workflow {
+        let content = format!("{}\njobs: {{}}\n", on);
+        serde_yaml::from_str(&content).unwrap()
+    }
+
+    fn paths(workflow: &Workflow, event: &str) -> Option<Vec<String>> {
+        workflow.on.as_ref()?.get(event)?.paths.clone()
+    }
+
+    #[test]
+    fn paths_filter_scopes_events_to_root() {
+        let mut w = workflow("on: [push, pull_request]");
+
+        assert!(paths_filter(&mut w, "packages/app"));
+        assert_eq!(paths(&w, "push"), Some(vec!["packages/app/**".into()]));
+        assert_eq!(
+            paths(&w, "pull_request"),
+            Some(vec!["packages/app/**".into()])
+        );
+    }
+
+    #[test]
+    fn paths_filter_keeps_existing_paths() {
+        let mut w = workflow("on:\n  push:\n    paths: [src/**]\n  pull_request:");
+
+        assert!(paths_filter(&mut w, "packages/app"));
+        assert_eq!(paths(&w, "push"), Some(vec!["src/**".into()]));
+        assert_eq!(
+            paths(&w, "pull_request"),
+            Some(vec!["packages/app/**".into()])
+        );
+    }
+
+    #[test]
+    fn paths_filter_leaves_filtered_workflows_unchanged() {
+        let mut w = workflow("on:\n  push:\n    paths: [src/**]");
+
+        assert!(!paths_filter(&mut w, "packages/app"));
+        assert_eq!(paths(&w, "push"), Some(vec!["src/**".into()]));
+    }
+
+    #[test]
+    fn paths_filter_negates_ignored_paths() {
+        let mut w = workflow("on:\n  push:\n    paths-ignore: [docs/**]");
+
+        assert!(paths_filter(&mut w, "packages/app"));
+        assert_eq!(
+            paths(&w, "push"),
+            Some(vec!["packages/app/**".into(), "!docs/**".into()])
+        );
+        assert!(w
+            .on
+            .as_ref()
+            .unwrap()
+            .get("push")
+            .unwrap()
+            .paths_ignore
+            .is_none());
+    }
+}
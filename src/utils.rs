@@ -1,27 +1,206 @@
-use crate::models::files::File;
+use crate::anchors;
+use crate::edit;
+use crate::header::Provenance;
+use crate::include::Includes;
+use crate::log;
+use crate::models::config::Config;
+use crate::models::files::{FileError, FileKind};
 use crate::models::workflow::Workflow;
+use crate::models::workspace::Workspace;
+use crate::naming;
+use crate::template;
+use crate::transforms;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Component, Path, PathBuf};
 
-pub fn copy_file(source: &Path, target_dir: &str, scope: &str) -> std::io::Result<()> {
-    let filename = target_filename(source, target_dir, scope);
-    to_void_result(fs::copy(source, filename))
+/// Source workflow with its template variables rendered and includes expanded.
+pub fn load_workflow(
+    source: &Path,
+    workspace: &Workspace,
+    config: &Config,
+) -> io::Result<Workflow> {
+    let content = fs::read_to_string(source)?;
+    let rendered = template::render(&content, source, workspace, config)?;
+
+    Ok(parse_workflow(&rendered, source, workspace, config)?.workflow)
+}
+
+struct Parsed {
+    workflow: Workflow,
+    has_includes: bool,
+    /// Keys merged from several maps
+    conflicts: Vec<anchors::Conflict>,
 }
 
-pub fn remove_file(source: &Path, target: &str, scope: &str) -> std::io::Result<()> {
-    let filename = target_filename(source, target, scope);
+/// Parses a rendered source workflow, expanding includes and merge keys.
+fn parse_workflow(
+    rendered: &str,
+    source: &Path,
+    workspace: &Workspace,
+    config: &Config,
+) -> io::Result<Parsed> {
+    let invalid = |err: FileError| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", normalize_path(source), err),
+        )
+    };
+
+    let mut value: serde_yaml::Value = FileKind::from_path(source)?
+        .parse(rendered)
+        .map_err(invalid)?;
+    let has_includes = Includes::new(source, workspace, config).expand(&mut value)?;
+    let conflicts = anchors::apply_merges(&mut value);
+    let workflow = serde_yaml::from_value(value).map_err(|err| invalid(err.into()))?;
+
+    Ok(Parsed {
+        workflow,
+        has_includes,
+        conflicts,
+    })
+}
+
+/// Content written in the target directory for the given source workflow. `names` is shared by
+/// the workflows of the workspace, see [`transforms::Names`].
+pub fn render_file(
+    source: &Path,
+    workspace: &Workspace,
+    config: &Config,
+    names: &transforms::Names,
+) -> std::io::Result<Vec<u8>> {
+    let original = fs::read(source)?;
+    let provenance = Provenance {
+        workspace: workspace.name.clone(),
+        source: normalize_path(source),
+        hash: hash(&original),
+    };
+
+    let rendered = template::render(
+        &String::from_utf8_lossy(&original),
+        source,
+        workspace,
+        config,
+    )?;
+    let mut content = provenance.render(config.header()).into_bytes();
+    let Parsed {
+        mut workflow,
+        has_includes,
+        conflicts,
+    } = parse_workflow(&rendered, source, workspace, config)?;
+
+    if config.expand_anchors && !conflicts.is_empty() {
+        let lines = anchors::Lines::new(&rendered);
+
+        for conflict in &conflicts {
+            log::warn(&format!(
+                "{}:{}",
+                normalize_path(source),
+                lines.describe(conflict)
+            ));
+        }
+    }
+
+    let ctx = transforms::Context {
+        source,
+        workspace,
+        config,
+        names,
+    };
+
+    let is_yaml = matches!(FileKind::from_path(source), Ok(FileKind::YAML));
+    let before = serde_yaml::to_value(&workflow).map_err(FileError::from)?;
+    let changed = transforms::apply(&mut workflow, &ctx)?;
+
+    if has_includes || config.expand_anchors || !is_yaml {
+        content.extend(workflow.to_yaml()?.into_bytes());
+    } else if changed {
+        // edits the source in place, keeping its comments and formatting
+        let after = serde_yaml::to_value(&workflow).map_err(FileError::from)?;
+        let edited = edit::apply(&rendered, &before, &after)
+            .filter(|edited| parses_to(edited, &after, source, workspace, config));
+        let edited = match edited {
+            Some(edited) => edited,
+            None => workflow.to_yaml()?,
+        };
 
-    if Path::new(&filename).exists() {
-        fs::remove_file(filename)?
+        content.extend(edited.into_bytes());
+    } else {
+        content.extend(rendered.into_bytes());
     }
 
-    Ok(())
+    Ok(content)
 }
 
-pub fn target_filename(source: &Path, target: &str, scope: &str) -> String {
-    let name = source.file_name().unwrap().to_str().unwrap();
+/// Whether the edited source reads as the transformed workflow. An edit the scanner got wrong
+/// falls back to the serialized workflow instead of generating a different one.
+fn parses_to(
+    edited: &str,
+    expected: &serde_yaml::Value,
+    source: &Path,
+    workspace: &Workspace,
+    config: &Config,
+) -> bool {
+    parse_workflow(edited, source, workspace, config)
+        .ok()
+        .and_then(|parsed| serde_yaml::to_value(&parsed.workflow).ok())
+        .is_some_and(|value| &value == expected)
+}
+
+pub fn target_filename(source: &Path, workspace: &Workspace, config: &Config) -> String {
+    let relative = relative_source(source, workspace);
+    let name = naming::filename(config.naming(), config.slug, &workspace.name, &relative);
 
-    format!("{}/{}--{}", target, scope, name)
+    format!("{}/{}", config.target, name)
+}
+
+/// Strips `./` segments and trailing slashes, using `/` as separator.
+pub fn normalize_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::CurDir => None,
+            c => c.as_os_str().to_str(),
+        })
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+/// Path of the source relative to the workspace directory, its file name for shared templates
+pub fn relative_source(source: &Path, workspace: &Workspace) -> PathBuf {
+    let dir = Path::new(&workspace.path);
+
+    if let Ok(relative) = source.strip_prefix(dir) {
+        return relative.into();
+    }
+
+    // watchers get absolute paths
+    match canonical(source).strip_prefix(canonical(dir)) {
+        Ok(relative) => relative.into(),
+        Err(_) => file_name(source).into(),
+    }
+}
+
+/// Canonical path, through its parent for removed files
+pub fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)
+            .map(|p| p.join(name))
+            .unwrap_or_else(|_| path.into()),
+        _ => path.into(),
+    })
+}
+
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .into()
+}
+
+/// Hex encoded sha256 of the given content
+pub fn hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
 }
 
 pub fn to_void_result<T>(r: std::io::Result<T>) -> std::io::Result<()> {
@@ -39,14 +218,144 @@ pub fn is_yaml(path: &str) -> bool {
 // checks if the given filepath is a valid workflow file
 // path must be the entire filepath
 pub fn is_workflow_file(filepath: &Path) -> bool {
-    let is_yaml = match filepath.extension() {
-        None => false,
-        Some(ext) => ext.eq("yaml") || ext.eq("yml"),
+    let kind = match FileKind::from_path(filepath) {
+        Ok(kind) => kind,
+        Err(_) => return false,
     };
 
-    if Workflow::load(filepath).is_err() {
-        return false;
+    // parsed loosely with the placeholders masked, their values may not fit the workflow model
+    // before rendering
+    fs::read_to_string(filepath)
+        .ok()
+        .and_then(|content| {
+            kind.parse::<serde_yaml::Mapping>(&template::mask(&content))
+                .ok()
+        })
+        .is_some_and(|workflow| workflow.contains_key("jobs"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::transforms::Transforms;
+    use serde_yaml::Value;
+
+    fn render(content: &str) -> Value {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("ci.yml");
+        fs::write(&source, content).unwrap();
+
+        let workspace = Workspace {
+            name: "app".into(),
+            path: normalize_path(dir.path()),
+            root: Some("packages/app".into()),
+            transforms: Transforms {
+                working_directory: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config = Config::new(".github/workflows");
+        let names = transforms::Names::default();
+        let rendered = render_file(&source, &workspace, &config, &names).unwrap();
+
+        serde_yaml::from_slice(&rendered).unwrap()
     }
 
-    is_yaml
+    #[test]
+    fn edits_in_place() {
+        let value = render(
+            "name: CI\non: push\njobs:\n  build:\n    runs-on: ubuntu-latest\n    steps:\n      - run: make\n",
+        );
+
+        assert_eq!(
+            value["defaults"]["run"]["working-directory"],
+            "packages/app"
+        );
+    }
+
+    #[test]
+    fn aliases_keep_their_value() {
+        let value = render(
+            "\
+name: CI
+on: push
+defaults: &defaults
+  run:
+    shell: bash
+jobs:
+  build:
+    runs-on: ubuntu-latest
+    defaults: *defaults
+    steps:
+      - run: make
+",
+        );
+
+        assert_eq!(
+            value["defaults"]["run"]["working-directory"],
+            "packages/app"
+        );
+        assert_eq!(
+            value["jobs"]["build"]["defaults"]["run"].get("working-directory"),
+            None
+        );
+    }
+
+    #[test]
+    fn detects_workflows_with_placeholders() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("ci.yml");
+        fs::write(
+            &source,
+            "name: {{ workspace.name }} build\non: push\njobs:\n  build:\n    runs-on: ubuntu-latest\n",
+        )
+        .unwrap();
+
+        assert!(is_workflow_file(&source));
+    }
+
+    #[test]
+    fn writes_other_formats_as_yaml() {
+        let sources = [
+            (
+                "ci.json",
+                r#"{ "name": "CI", "on": "push", "jobs": { "build": { "runs-on": "ubuntu-latest", "steps": [{ "run": "make" }] } } }"#,
+            ),
+            (
+                "ci.json5",
+                "{ name: 'CI', on: 'push', jobs: { build: { 'runs-on': 'ubuntu-latest', steps: [{ run: 'make' }] } }, }",
+            ),
+            (
+                "ci.toml",
+                "name = \"CI\"\non = \"push\"\n\n[jobs.build]\nruns-on = \"ubuntu-latest\"\nsteps = [{ run = \"make\" }]\n",
+            ),
+        ];
+
+        for (name, content) in sources {
+            let dir = tempfile::tempdir().unwrap();
+            let source = dir.path().join(name);
+            fs::write(&source, content).unwrap();
+
+            let workspace = Workspace {
+                name: "app".into(),
+                path: normalize_path(dir.path()),
+                ..Default::default()
+            };
+            let config = Config::new(".github/workflows");
+            let names = transforms::Names::default();
+            let rendered = render_file(&source, &workspace, &config, &names).unwrap();
+            let rendered = String::from_utf8(rendered).unwrap();
+            let value: Value = serde_yaml::from_str(&rendered).unwrap();
+
+            assert!(rendered.contains("runs-on: ubuntu-latest\n"), "{}", name);
+            assert_eq!(value["name"], "CI", "{}", name);
+            assert_eq!(
+                value["jobs"]["build"]["steps"][0]["run"], "make",
+                "{}",
+                name
+            );
+            assert!(target_filename(&source, &workspace, &config).ends_with("app--ci.yml"));
+        }
+    }
 }
@@ -1,24 +1,53 @@
 //! src/main.rs
 use colored::*;
-use notify::event::{CreateKind, DataChange, ModifyKind, RenameMode};
 use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use crate::actions;
+use crate::cli::{ConflictFlags, PreviewFlags};
+use crate::filter;
+use crate::filter::Filter;
+use crate::include;
 use crate::log;
+use crate::models::config::Config;
+use crate::models::manifest::Manifest;
 use crate::models::workspace::Workspace;
+use crate::plan;
+use crate::sync;
+use crate::sync::{Action, ConflictOptions, PreviewOptions};
 use crate::utils;
 
+/// Watches the config file, and the aggregate workflow source, to regenerate the aggregate
+/// workflow. Workspace watchers are not updated.
+///
+/// Their directories are watched, editors often replace files instead of writing them.
 pub fn watch_config(path: &str) -> notify::Result<()> {
     let (tx, rx) = std::sync::mpsc::channel();
     let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())?;
+    let config_path = fs::canonicalize(path)?;
 
-    watcher.watch(Path::new(path).as_ref(), RecursiveMode::Recursive)?;
+    watcher.watch(parent(&config_path), RecursiveMode::NonRecursive)?;
+
+    let mut aggregate_source = None;
+    watch_aggregate(&mut watcher, &mut aggregate_source, path)?;
 
     for res in rx {
         match res {
             Ok(event) => {
-                if let EventKind::Modify(ModifyKind::Data(DataChange::Content)) = event.kind {
-                    log::warn("Changes detected in the config file. Please restart")
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    continue;
+                }
+
+                if event.paths.contains(&config_path) {
+                    log::warn("Changes detected in the config file. Please restart");
+                    watch_aggregate(&mut watcher, &mut aggregate_source, path)?;
+                    regenerate_aggregate(path);
+                } else if aggregate_source
+                    .as_ref()
+                    .is_some_and(|s| event.paths.contains(s))
+                {
+                    regenerate_aggregate(path);
                 }
             }
             Err(err) => log::error("Something went wrong:", err),
@@ -28,7 +57,70 @@ pub fn watch_config(path: &str) -> notify::Result<()> {
     Ok(())
 }
 
-pub fn watch_sync(workspace: Workspace, target: &str) -> notify::Result<()> {
+fn parent(path: &Path) -> &Path {
+    path.parent().unwrap_or(path)
+}
+
+/// Follows the aggregate source configured in the config file. The directory of the config
+/// file stays watched when the source lives next to it.
+fn watch_aggregate(
+    watcher: &mut RecommendedWatcher,
+    current: &mut Option<PathBuf>,
+    config_path: &str,
+) -> notify::Result<()> {
+    let config_dir = fs::canonicalize(config_path)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let source = Config::read(Path::new(config_path))
+        .ok()
+        .and_then(|c| c.aggregate)
+        .and_then(|a| fs::canonicalize(a.source).ok());
+
+    if source == *current {
+        return Ok(());
+    }
+
+    if let Some(previous) = current.take() {
+        let dir = parent(&previous);
+        let is_needed = dir == config_dir || source.as_deref().map(parent) == Some(dir);
+
+        if !is_needed {
+            watcher.unwatch(dir).ok();
+        }
+    }
+
+    if let Some(source) = &source {
+        if parent(source) != config_dir {
+            watcher.watch(parent(source), RecursiveMode::NonRecursive)?;
+        }
+    }
+
+    *current = source;
+    Ok(())
+}
+
+fn regenerate_aggregate(config_path: &str) {
+    let config = match Config::read(Path::new(config_path)) {
+        Ok(config) => config,
+        Err(err) => return log::error("Could not read config file", err),
+    };
+
+    let preview = PreviewFlags::default();
+
+    if let Err(err) = actions::aggregate(&config, &preview, &ConflictFlags::default()) {
+        log::warn(&err.to_string())
+    }
+}
+
+/// Watches the workspace directory, the shared templates it gets and the fragments its
+/// workflows include, syncing the workspace on every change as `copy` does.
+pub fn watch_sync(
+    workspace: Workspace,
+    config: &Config,
+    flags: &PreviewFlags,
+    conflict_flags: &ConflictFlags,
+) -> notify::Result<()> {
     println!(
         "[{}] {} for {}",
         "WATCH".bold().blue(),
@@ -39,42 +131,92 @@ pub fn watch_sync(workspace: Workspace, target: &str) -> notify::Result<()> {
     let (tx, rx) = std::sync::mpsc::channel();
     let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())?;
     let path = Path::new(&workspace.path);
+    let workspace_dir = fs::canonicalize(path)?;
+    let mut fragments = Fragments::default();
+    let preview = PreviewOptions::from(flags);
+    let conflicts = ConflictOptions::from(conflict_flags);
+    // `copy` already reported the files edited by hand
+    let mut reported: Vec<(PathBuf, String)> = Manifest::read(&config.target)
+        .map(|manifest| {
+            manifest
+                .workspace(&workspace.name)
+                .map(|(name, _)| Path::new(&config.target).join(name))
+                .filter(|f| manifest.is_modified(f))
+                .map(|f| {
+                    let hash = content_hash(&f);
+                    (f, hash)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
 
     watcher.watch(path.as_ref(), RecursiveMode::Recursive)?;
+    fragments.refresh(
+        &mut watcher,
+        &workspace,
+        config,
+        &plan::sources(&workspace, config).files,
+    )?;
+
+    // templates are read from the top level of their directory only
+    let templates_dir = config
+        .templates
+        .as_ref()
+        .filter(|t| t.matches(&workspace))
+        .and_then(|t| fs::canonicalize(&t.path).ok());
+
+    if let Some(dir) = &templates_dir {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
 
     for res in rx {
         match res {
             Ok(event) => {
-                if event.paths.first().is_none() {
-                    log::warn("No paths...");
+                if !matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) {
                     continue;
                 }
 
-                let path = event.paths.first().unwrap();
+                // other files in the directories of the fragments are ignored, as well as
+                // workflows left out by the workspace filter
+                let filter = Filter::new(&workspace);
+                let relevant: Vec<&PathBuf> = event
+                    .paths
+                    .iter()
+                    .filter(|path| {
+                        fragments.contains(path)
+                            || path.ends_with(filter::IGNORE_FILE)
+                            || templates_dir.as_ref().is_some_and(|dir| {
+                                utils::canonical(path).parent() == Some(dir.as_path())
+                            })
+                            || (utils::canonical(path).starts_with(&workspace_dir)
+                                && !filter.is_ignored(path))
+                    })
+                    .collect();
 
-                if path.is_dir() {
+                if relevant.is_empty() {
                     continue;
                 }
 
-                match event.kind {
-                    EventKind::Remove(_) => utils::remove_file(path, target, &workspace.name)?,
-                    EventKind::Create(CreateKind::File)
-                    | EventKind::Modify(ModifyKind::Data(DataChange::Content)) => {
-                        utils::copy_file(path, target, &workspace.name)?
-                    }
-                    EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
-                        let mut iter = event.paths.iter();
-                        let from = iter.next().unwrap();
-                        let to = iter.next().unwrap();
-
-                        utils::copy_file(to, target, &workspace.name)?;
-                        utils::remove_file(from, target, &workspace.name)?;
-                    }
-                    EventKind::Modify(ModifyKind::Name(RenameMode::Any)) => {
-                        log::warn("Renaming is not supported yet! Please see https://github.com/notify-rs/notify/issues/261");
-                    }
-                    _ => continue,
-                }
+                // an edited fragment only affects the workflows including it
+                let dependents = fragments.dependents(&relevant);
+                let plan = match &dependents {
+                    Some(sources) => plan::workflows(&workspace, config, sources),
+                    None => plan::workspace(&workspace, config),
+                };
+                let sources = dependents.unwrap_or_else(|| plan::sources(&workspace, config).files);
+
+                sync_workspace(
+                    &workspace,
+                    config,
+                    plan,
+                    &mut reported,
+                    &preview,
+                    &conflicts,
+                );
+                fragments.refresh(&mut watcher, &workspace, config, &sources)?;
             }
             Err(e) => log::error("watch error:", e),
         }
@@ -82,3 +224,137 @@ pub fn watch_sync(workspace: Workspace, target: &str) -> notify::Result<()> {
 
     Ok(())
 }
+
+/// Applies the changes planned for the workspace, if any. Files edited by hand are reported
+/// once, until they are edited again: `reported` holds them with the hash of their content.
+fn sync_workspace(
+    workspace: &Workspace,
+    config: &Config,
+    plan: std::io::Result<plan::Plan>,
+    reported: &mut Vec<(PathBuf, String)>,
+    flags: &PreviewOptions,
+    conflict_flags: &ConflictOptions,
+) {
+    let mut changes =
+        match plan.and_then(|plan| sync::changes(workspace, config, plan, conflict_flags)) {
+            Ok(changes) => changes,
+            Err(err) => return log::warn(&err.to_string()),
+        };
+
+    let previous = std::mem::take(reported);
+
+    changes.actions.retain(|action| {
+        let file = match action {
+            Action::Conflict(output) | Action::Merge(output) => &output.target,
+            Action::Edited(file) | Action::Unrecorded(file) => file,
+            _ => return true,
+        };
+
+        let entry = (file.clone(), content_hash(file));
+        let is_new = !previous.contains(&entry);
+
+        reported.push(entry);
+        is_new
+    });
+
+    let is_up_to_date = changes.actions.iter().all(|action| match action {
+        Action::UpToDate(_) => true,
+        Action::Remove(file) => !file.exists(),
+        _ => false,
+    });
+
+    if is_up_to_date {
+        return;
+    }
+
+    if !flags.is_verbose() {
+        for action in &changes.actions {
+            if let Action::Write(output, _) = action {
+                println!(
+                    "[{}] {}",
+                    "SYNC".bold().green(),
+                    output.target.display().to_string().underline().blue()
+                );
+            }
+        }
+    }
+
+    if let Err(err) = sync::apply(&changes, workspace, config, flags) {
+        log::warn(&err.to_string())
+    }
+}
+
+fn content_hash(path: &Path) -> String {
+    fs::read(path).map(|c| utils::hash(&c)).unwrap_or_default()
+}
+
+/// Files included by the workflows of a workspace with the workflows including them, and the
+/// directories watched for them
+#[derive(Default)]
+struct Fragments {
+    files: Vec<(PathBuf, Vec<PathBuf>)>,
+    dirs: Vec<PathBuf>,
+}
+
+impl Fragments {
+    fn contains(&self, path: &Path) -> bool {
+        let path = utils::canonical(path);
+
+        self.files.iter().any(|(f, _)| *f == path)
+    }
+
+    /// Existing workflows including the paths, `None` unless they are all fragments
+    fn dependents(&self, paths: &[&PathBuf]) -> Option<Vec<PathBuf>> {
+        let mut dependents = Vec::new();
+
+        for path in paths {
+            let path = utils::canonical(path);
+            let (_, sources) = self.files.iter().find(|(f, _)| *f == path)?;
+
+            if utils::is_workflow_file(&path) {
+                return None;
+            }
+
+            for source in sources.iter().filter(|s| s.exists()) {
+                if !dependents.contains(source) {
+                    dependents.push(source.clone());
+                }
+            }
+        }
+
+        Some(dependents)
+    }
+
+    /// Watches the fragments included by the given workflows. Fragments no longer included
+    /// are kept, a fragment being written can fail to parse and hide its own includes.
+    fn refresh(
+        &mut self,
+        watcher: &mut RecommendedWatcher,
+        workspace: &Workspace,
+        config: &Config,
+        sources: &[PathBuf],
+    ) -> notify::Result<()> {
+        let files = sources.iter().flat_map(|source| {
+            include::dependencies(source, workspace, config)
+                .into_iter()
+                .map(move |f| (utils::canonical(&f), source))
+        });
+
+        for (file, source) in files {
+            let dir = parent(&file).to_path_buf();
+
+            match self.files.iter_mut().find(|(f, _)| *f == file) {
+                Some((_, sources)) if !sources.contains(source) => sources.push(source.clone()),
+                Some(_) => {}
+                None => self.files.push((file, vec![source.clone()])),
+            }
+
+            if !self.dirs.contains(&dir) {
+                watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+                self.dirs.push(dir);
+            }
+        }
+
+        Ok(())
+    }
+}